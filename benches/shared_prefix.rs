@@ -0,0 +1,88 @@
+//! Compares the `shared_prefix` implementations on 128-bit and 256-bit keys.
+//!
+//! Run with `cargo bench`.
+
+extern crate binary_prefix;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use binary_prefix::bits::shared_prefix_len;
+use binary_prefix::BitString;
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn bench<F: FnMut() -> usize>(name: &str, mut f: F) {
+    // warm up
+    for _ in 0..ITERATIONS / 10 {
+        black_box(f());
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let elapsed = start.elapsed();
+
+    println!("{:<28} {:>8.2} ns/iter", name, per_iter(elapsed));
+}
+
+fn per_iter(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1e9 / f64::from(ITERATIONS)
+}
+
+/// The bit-at-a-time loop `shared_prefix` started out with, kept here as the baseline.
+fn baseline_shared_prefix<'a>(start: &'a [bool], end: &[bool]) -> &'a [bool] {
+    let pairs = start.iter().zip(end);
+    let mut slice_end = 0;
+    for pair in pairs {
+        if pair.0 == pair.1 {
+            slice_end += 1;
+        } else {
+            break;
+        }
+    }
+    &start[0..slice_end]
+}
+
+/// Two keys of `bytes` bytes that differ only in their last bit.
+fn keys(bytes: usize) -> (Vec<u8>, Vec<u8>) {
+    let a: Vec<u8> = (0..bytes).map(|i| i as u8).collect();
+    let mut b = a.clone();
+    b[bytes - 1] ^= 1;
+    (a, b)
+}
+
+fn main() {
+    for &bytes in &[16, 32] {
+        let (a, b) = keys(bytes);
+        let (packed_a, packed_b) = (BitString::from_bytes(&a), BitString::from_bytes(&b));
+        let (bools_a, bools_b) = (packed_a.to_vec(), packed_b.to_vec());
+
+        println!("{}-bit keys", bytes * 8);
+        bench("bool slice loop (baseline)", || {
+            baseline_shared_prefix(black_box(&bools_a), black_box(&bools_b)).len()
+        });
+        bench("BitStr bit-at-a-time", || {
+            let (a, b) = (black_box(&packed_a), black_box(&packed_b));
+            a.iter().zip(b.iter()).take_while(|&(x, y)| x == y).count()
+        });
+        bench("BitStr word-at-a-time", || {
+            let (a, b) = (black_box(&packed_a), black_box(&packed_b));
+            a.as_bit_str().shared_prefix(b.as_bit_str()).len()
+        });
+        bench("BitStr unaligned view", || {
+            let (a, b) = (black_box(&packed_a), black_box(&packed_b));
+            a.slice(1..).shared_prefix(b.slice(1..)).len()
+        });
+        if bytes == 16 {
+            let mut word_a = [0; 16];
+            let mut word_b = [0; 16];
+            word_a.copy_from_slice(&a);
+            word_b.copy_from_slice(&b);
+            let (int_a, int_b) = (u128::from_be_bytes(word_a), u128::from_be_bytes(word_b));
+            bench("u128 fast path", || shared_prefix_len(black_box(int_a), black_box(int_b)));
+        }
+        println!();
+    }
+}
@@ -0,0 +1,895 @@
+//! Packed bit strings.
+//!
+//! [`BitString`] is an owned sequence of bits stored most-significant first in `u64` words.
+//! [`BitStr`] is a cheap, copyable view into a `BitString` (or a part of one),
+//! in the same way that `&str` relates to `String`.
+//!
+//! The prefix functions of this crate are implemented on these types;
+//! the `&[bool]` versions at the crate root convert to and from them.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::{Add, Bound, Index, RangeBounds};
+
+use error::{validate_range, RangePrefixError};
+
+const WORD_BITS: usize = 64;
+
+static TRUE: bool = true;
+static FALSE: bool = false;
+
+/// Returns a word with the top `n` bits set.
+fn mask(n: usize) -> u64 {
+    match n {
+        0 => 0,
+        n if n >= WORD_BITS => !0,
+        n => !0 << (WORD_BITS - n),
+    }
+}
+
+/// An owned, packed string of bits.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::BitString;
+///
+/// let mut bits = BitString::from_bytes(b"a");
+/// bits.push(true);
+///
+/// bits.to_string();
+/// // "011000011"
+/// ```
+#[derive(Clone, Default)]
+pub struct BitString {
+    // bits past `len` are always zero
+    words: Vec<u64>,
+    len: usize,
+}
+
+/// A borrowed view into a [`BitString`].
+#[derive(Clone, Copy)]
+pub struct BitStr<'a> {
+    words: &'a [u64],
+    offset: usize,
+    len: usize,
+}
+
+/// Iterator over the bits of a [`BitStr`].
+#[derive(Clone)]
+pub struct Iter<'a> {
+    bits: BitStr<'a>,
+    front: usize,
+    back: usize,
+}
+
+impl BitString {
+    /// Creates an empty bit string.
+    pub fn new() -> BitString {
+        BitString::default()
+    }
+
+    /// Creates an empty bit string with room for `bits` bits.
+    pub fn with_capacity(bits: usize) -> BitString {
+        BitString {
+            words: Vec::with_capacity(bits.div_ceil(WORD_BITS)),
+            len: 0,
+        }
+    }
+
+    /// Creates a bit string of `len` zeros.
+    pub fn zeros(len: usize) -> BitString {
+        BitString {
+            words: vec![0; len.div_ceil(WORD_BITS)],
+            len,
+        }
+    }
+
+    /// Creates a bit string from bytes, most significant bit first.
+    pub fn from_bytes(bytes: &[u8]) -> BitString {
+        let mut out = BitString::with_capacity(bytes.len() * 8);
+        for chunk in bytes.chunks(8) {
+            let mut word = 0;
+            for (i, byte) in chunk.iter().enumerate() {
+                word |= u64::from(*byte) << (56 - 8 * i);
+            }
+            out.push_chunk(word, chunk.len() * 8);
+        }
+        out
+    }
+
+    /// Borrows the whole bit string as a [`BitStr`].
+    pub fn as_bit_str(&self) -> BitStr<'_> {
+        BitStr {
+            words: &self.words,
+            offset: 0,
+            len: self.len,
+        }
+    }
+
+    /// Returns the number of bits.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no bits.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the bit at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<bool> {
+        self.as_bit_str().get(index)
+    }
+
+    /// Sets the bit at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, bit: bool) {
+        assert!(index < self.len, "bit index {} out of range for length {}", index, self.len);
+        let word = &mut self.words[index / WORD_BITS];
+        let flag = 1 << (WORD_BITS - 1 - index % WORD_BITS);
+        if bit {
+            *word |= flag;
+        } else {
+            *word &= !flag;
+        }
+    }
+
+    /// Appends a bit.
+    pub fn push(&mut self, bit: bool) {
+        self.push_chunk(if bit { 1 << (WORD_BITS - 1) } else { 0 }, 1);
+    }
+
+    /// Removes the last bit and returns it, or `None` if empty.
+    pub fn pop(&mut self) -> Option<bool> {
+        let last = self.get(self.len.checked_sub(1)?);
+        self.truncate(self.len - 1);
+        last
+    }
+
+    /// Shortens the bit string to `len` bits. Has no effect if it is already shorter.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        self.words.truncate(len.div_ceil(WORD_BITS));
+        if let Some(last) = self.words.last_mut() {
+            *last &= mask(len - (len - 1) / WORD_BITS * WORD_BITS);
+        }
+        self.len = len;
+    }
+
+    /// Appends all bits of `other`.
+    pub fn extend_from_bit_str(&mut self, other: BitStr) {
+        let mut pos = 0;
+        while pos < other.len {
+            let n = (other.len - pos).min(WORD_BITS);
+            self.push_chunk(other.chunk(pos), n);
+            pos += n;
+        }
+    }
+
+    /// Appends the top `n` bits of `chunk`.
+    pub(crate) fn push_chunk(&mut self, chunk: u64, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let chunk = chunk & mask(n);
+        let used = self.len % WORD_BITS;
+        if used == 0 {
+            self.words.push(chunk);
+        } else {
+            let last = self.words.len() - 1;
+            self.words[last] |= chunk >> used;
+            if used + n > WORD_BITS {
+                self.words.push(chunk << (WORD_BITS - used));
+            }
+        }
+        self.len += n;
+    }
+
+    /// Returns a view of the bits in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> BitStr<'_> {
+        self.as_bit_str().slice(range)
+    }
+
+    /// Returns an iterator over the bits.
+    pub fn iter(&self) -> Iter<'_> {
+        self.as_bit_str().iter()
+    }
+
+    /// Copies the bits into a vector of booleans.
+    pub fn to_vec(&self) -> Vec<bool> {
+        self.as_bit_str().to_vec()
+    }
+
+    /// Packs the bits into bytes, filling the last byte with trailing zeros.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.as_bit_str().to_bytes()
+    }
+}
+
+impl<'a> BitStr<'a> {
+    /// Returns the number of bits.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no bits.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the bit at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index >= self.len {
+            return None;
+        }
+        let pos = self.offset + index;
+        Some(self.words[pos / WORD_BITS] >> (WORD_BITS - 1 - pos % WORD_BITS) & 1 == 1)
+    }
+
+    /// Returns a view of the bits in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> BitStr<'a> {
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i + 1,
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => self.len,
+        };
+        assert!(start <= end, "slice index starts at {} but ends at {}", start, end);
+        assert!(end <= self.len, "range end index {} out of range for length {}", end, self.len);
+        BitStr {
+            words: self.words,
+            offset: self.offset + start,
+            len: end - start,
+        }
+    }
+
+    /// Returns an iterator over the bits.
+    pub fn iter(&self) -> Iter<'a> {
+        Iter {
+            bits: *self,
+            front: 0,
+            back: self.len,
+        }
+    }
+
+    /// Returns `true` if `prefix` is a prefix of this bit string.
+    pub fn starts_with(&self, prefix: BitStr) -> bool {
+        prefix.len <= self.len && self.slice(..prefix.len) == prefix
+    }
+
+    /// Finds the longest shared prefix with `other`, as a view of `self`.
+    ///
+    /// Compares a word at a time: each pair of 64-bit chunks is XORed and the
+    /// first difference is found with `leading_zeros`.
+    pub fn shared_prefix(&self, other: BitStr) -> BitStr<'a> {
+        let limit = self.len.min(other.len);
+        let mut len = 0;
+        while len < limit {
+            let diff = (self.chunk(len) ^ other.chunk(len)).leading_zeros() as usize;
+            if diff < WORD_BITS {
+                len += diff;
+                break;
+            }
+            len += WORD_BITS;
+        }
+        self.slice(..len.min(limit))
+    }
+
+    /// Pads the bit string with leading zeros up to `size` bits.
+    pub fn pad(&self, size: usize) -> BitString {
+        let mut out = BitString::zeros(size.saturating_sub(self.len));
+        out.extend_from_bit_str(*self);
+        out
+    }
+
+    /// Concatenates `other` onto a copy of this bit string.
+    pub fn concat(&self, other: BitStr) -> BitString {
+        let mut out = BitString::with_capacity(self.len + other.len);
+        out.extend_from_bit_str(*self);
+        out.extend_from_bit_str(other);
+        out
+    }
+
+    /// Copies the bits into an owned [`BitString`].
+    pub fn to_bit_string(&self) -> BitString {
+        let mut out = BitString::with_capacity(self.len);
+        out.extend_from_bit_str(*self);
+        out
+    }
+
+    /// Copies the bits into a vector of booleans.
+    pub fn to_vec(&self) -> Vec<bool> {
+        self.iter().collect()
+    }
+
+    /// Packs the bits into bytes, filling the last byte with trailing zeros.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        (0..self.len.div_ceil(8))
+            .map(|i| (self.chunk(i * 8) >> 56) as u8)
+            .collect()
+    }
+
+    /// Returns the index of the first bit equal to `bit`.
+    pub(crate) fn position(&self, bit: bool) -> Option<usize> {
+        let mut pos = 0;
+        while pos < self.len {
+            let n = (self.len - pos).min(WORD_BITS);
+            let chunk = if bit { self.chunk(pos) } else { !self.chunk(pos) & mask(n) };
+            if chunk != 0 {
+                return Some(pos + chunk.leading_zeros() as usize);
+            }
+            pos += n;
+        }
+        None
+    }
+
+    /// Returns the index of the last bit equal to `bit`.
+    pub(crate) fn rposition(&self, bit: bool) -> Option<usize> {
+        let mut end = self.len;
+        while end > 0 {
+            let n = end.min(WORD_BITS);
+            let pos = end - n;
+            let chunk = if bit { self.chunk(pos) } else { !self.chunk(pos) & mask(n) };
+            if chunk != 0 {
+                return Some(pos + WORD_BITS - 1 - chunk.trailing_zeros() as usize);
+            }
+            end = pos;
+        }
+        None
+    }
+
+    /// Returns the 64 bits starting at `pos`, with bits past the end set to zero.
+    pub(crate) fn chunk(&self, pos: usize) -> u64 {
+        let abs = self.offset + pos;
+        let (word, shift) = (abs / WORD_BITS, abs % WORD_BITS);
+        let high = self.words.get(word).map_or(0, |w| w << shift);
+        let low = match shift {
+            0 => 0,
+            _ => self.words.get(word + 1).map_or(0, |w| w >> (WORD_BITS - shift)),
+        };
+        (high | low) & mask(self.len.saturating_sub(pos))
+    }
+}
+
+/// Unsigned integers that can be compared as fixed-width bit strings without allocating.
+pub trait PrefixWord: Copy {
+    /// The number of bits in the integer.
+    const BITS: usize;
+
+    /// Returns the length of the longest shared prefix of `self` and `other`.
+    fn shared_prefix_len(self, other: Self) -> usize;
+}
+
+macro_rules! prefix_word {
+    ($($ty:ty),*) => {$(
+        impl PrefixWord for $ty {
+            const BITS: usize = <$ty>::BITS as usize;
+
+            fn shared_prefix_len(self, other: $ty) -> usize {
+                (self ^ other).leading_zeros() as usize
+            }
+        }
+    )*};
+}
+
+prefix_word!(u8, u16, u32, u64, u128);
+
+/// Finds the length of the longest shared prefix of two integer keys, most significant bit first.
+///
+/// This is the allocation-free fast path for keys that fit in a primitive integer.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::bits::shared_prefix_len;
+///
+/// shared_prefix_len(0b1011_0000u8, 0b1010_1111u8);
+/// // 3
+/// ```
+pub fn shared_prefix_len<W: PrefixWord>(a: W, b: W) -> usize {
+    a.shared_prefix_len(b)
+}
+
+/// Finds the two longest prefixes that cover a binary range.
+///
+/// See [`range_prefix`](../fn.range_prefix.html) for details.
+///
+/// # Panics
+///
+/// Panics if the range is rejected by [`try_range_prefix`].
+pub fn range_prefix<'a, 'b>(start: BitStr<'a>, end: BitStr<'b>) -> (BitStr<'a>, BitStr<'b>) {
+    try_range_prefix(start, end).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Finds the two longest prefixes that cover a binary range, or reports why the range is malformed.
+///
+/// See [`try_range_prefix`](../fn.try_range_prefix.html) for details.
+pub fn try_range_prefix<'a, 'b>(
+    start: BitStr<'a>,
+    end: BitStr<'b>,
+) -> Result<(BitStr<'a>, BitStr<'b>), RangePrefixError> {
+    validate_range(&start, &end)?;
+
+    let segment_len = end.len();
+
+    let base_len = start.shared_prefix(end).len();
+
+    let start_special = find_seq(false, start.slice(base_len..segment_len));
+    let end_special = find_seq(true, end.slice(base_len..segment_len));
+
+    Ok((start.slice(..base_len + start_special), end.slice(..base_len + end_special)))
+}
+
+fn find_seq(initial: bool, collection: BitStr) -> usize {
+    if collection.get(0) != Some(initial) {
+        return 0;
+    }
+    let rest = collection.slice(1..);
+    1 + rest.position(initial).unwrap_or(rest.len())
+}
+
+/// Finds the minimal set of prefixes whose union is exactly the inclusive range `[start, end]`.
+///
+/// See [`range_cover`](../fn.range_cover.html) for details.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::bits::range_cover;
+/// use binary_prefix::BitString;
+///
+/// let a = BitString::from_bytes(&[0x12]);
+/// let b = BitString::from_bytes(&[0x3f]);
+///
+/// range_cover(a.as_bit_str(), b.as_bit_str());
+/// // Ok([0001001, 000101, 00011, 001])
+/// ```
+pub fn range_cover(start: BitStr, end: BitStr) -> Result<Vec<BitString>, RangePrefixError> {
+    validate_range(&start, &end)?;
+
+    let base_len = start.shared_prefix(end).len();
+    if base_len == start.len() {
+        return Ok(vec![start.to_bit_string()]);
+    }
+
+    let start_tail = start.rposition(true).filter(|&i| i > base_len);
+    let end_tail = end.rposition(false).filter(|&i| i > base_len);
+    if start_tail.is_none() && end_tail.is_none() {
+        return Ok(vec![start.slice(..base_len).to_bit_string()]);
+    }
+
+    let mut cover = Vec::new();
+
+    // lower half: keys under `base + 0` that are not below `start`
+    match start_tail {
+        Some(last_one) => {
+            cover.push(start.slice(..=last_one).to_bit_string());
+            for i in (base_len + 1..last_one).rev() {
+                if !start[i] {
+                    cover.push(sibling(start.slice(..=i)));
+                }
+            }
+        }
+        None => cover.push(start.slice(..=base_len).to_bit_string()),
+    }
+
+    // upper half: keys under `base + 1` that are not above `end`
+    match end_tail {
+        Some(last_zero) => {
+            for i in base_len + 1..last_zero {
+                if end[i] {
+                    cover.push(sibling(end.slice(..=i)));
+                }
+            }
+            cover.push(end.slice(..=last_zero).to_bit_string());
+        }
+        None => cover.push(end.slice(..=base_len).to_bit_string()),
+    }
+
+    Ok(cover)
+}
+
+/// Finds the smallest bit string greater than every bit string starting with `prefix`.
+///
+/// The keys starting with `prefix` are exactly the half-open range `[prefix, successor)`.
+/// A prefix made only of ones, including the empty prefix, has no successor, so its range
+/// is unbounded above.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::bits::prefix_successor;
+/// use binary_prefix::BitString;
+///
+/// let prefix: BitString = "01011".chars().map(|c| c == '1').collect();
+///
+/// prefix_successor(prefix.as_bit_str());
+/// // Some(011)
+/// ```
+pub fn prefix_successor(prefix: BitStr) -> Option<BitString> {
+    let last_zero = prefix.rposition(false)?;
+    let mut next = prefix.slice(..=last_zero).to_bit_string();
+    next.set(last_zero, true);
+    Some(next)
+}
+
+pub(crate) fn sibling(prefix: BitStr) -> BitString {
+    let mut out = prefix.to_bit_string();
+    if let Some(last) = out.len().checked_sub(1) {
+        let bit = out[last];
+        out.set(last, !bit);
+    }
+    out
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        self.bits.get(self.front - 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<bool> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.bits.get(self.back)
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+impl<'a> IntoIterator for BitStr<'a> {
+    type Item = bool;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a BitString {
+    type Item = bool;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> Index<usize> for BitStr<'a> {
+    type Output = bool;
+
+    fn index(&self, index: usize) -> &bool {
+        match self.get(index) {
+            Some(true) => &TRUE,
+            Some(false) => &FALSE,
+            None => panic!("bit index {} out of range for length {}", index, self.len),
+        }
+    }
+}
+
+impl Index<usize> for BitString {
+    type Output = bool;
+
+    fn index(&self, index: usize) -> &bool {
+        match self.get(index) {
+            Some(true) => &TRUE,
+            Some(false) => &FALSE,
+            None => panic!("bit index {} out of range for length {}", index, self.len),
+        }
+    }
+}
+
+impl<'a> PartialEq for BitStr<'a> {
+    fn eq(&self, other: &BitStr) -> bool {
+        self.len == other.len
+            && (0..self.len)
+                .step_by(WORD_BITS)
+                .all(|pos| self.chunk(pos) == other.chunk(pos))
+    }
+}
+
+impl<'a> Eq for BitStr<'a> {}
+
+impl<'a> Ord for BitStr<'a> {
+    fn cmp(&self, other: &BitStr) -> Ordering {
+        let shared = self.shared_prefix(*other).len();
+        match (self.get(shared), other.get(shared)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => self.len.cmp(&other.len),
+        }
+    }
+}
+
+impl<'a> PartialOrd for BitStr<'a> {
+    fn partial_cmp(&self, other: &BitStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Hash for BitStr<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for pos in (0..self.len).step_by(WORD_BITS) {
+            self.chunk(pos).hash(state);
+        }
+    }
+}
+
+impl PartialEq for BitString {
+    fn eq(&self, other: &BitString) -> bool {
+        self.as_bit_str() == other.as_bit_str()
+    }
+}
+
+impl Eq for BitString {}
+
+impl<'a> PartialEq<BitStr<'a>> for BitString {
+    fn eq(&self, other: &BitStr<'a>) -> bool {
+        self.as_bit_str() == *other
+    }
+}
+
+impl<'a> PartialEq<BitString> for BitStr<'a> {
+    fn eq(&self, other: &BitString) -> bool {
+        *self == other.as_bit_str()
+    }
+}
+
+impl Ord for BitString {
+    fn cmp(&self, other: &BitString) -> Ordering {
+        self.as_bit_str().cmp(&other.as_bit_str())
+    }
+}
+
+impl PartialOrd for BitString {
+    fn partial_cmp(&self, other: &BitString) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for BitString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bit_str().hash(state);
+    }
+}
+
+impl<'a> fmt::Display for BitStr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for bit in self.iter() {
+            f.write_str(if bit { "1" } else { "0" })?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Debug for BitStr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BitStr({})", self)
+    }
+}
+
+impl fmt::Display for BitString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_bit_str().fmt(f)
+    }
+}
+
+impl fmt::Debug for BitString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BitString({})", self.as_bit_str())
+    }
+}
+
+impl<'a> From<&'a BitString> for BitStr<'a> {
+    fn from(bits: &'a BitString) -> BitStr<'a> {
+        bits.as_bit_str()
+    }
+}
+
+impl<'a> From<BitStr<'a>> for BitString {
+    fn from(bits: BitStr<'a>) -> BitString {
+        bits.to_bit_string()
+    }
+}
+
+impl<'a> From<&'a [bool]> for BitString {
+    fn from(bits: &'a [bool]) -> BitString {
+        bits.iter().cloned().collect()
+    }
+}
+
+impl From<Vec<bool>> for BitString {
+    fn from(bits: Vec<bool>) -> BitString {
+        BitString::from(&bits[..])
+    }
+}
+
+impl FromIterator<bool> for BitString {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> BitString {
+        let mut out = BitString::new();
+        out.extend(iter);
+        out
+    }
+}
+
+impl Extend<bool> for BitString {
+    fn extend<I: IntoIterator<Item = bool>>(&mut self, iter: I) {
+        for bit in iter {
+            self.push(bit);
+        }
+    }
+}
+
+impl<'a> Add<BitStr<'a>> for BitString {
+    type Output = BitString;
+
+    fn add(mut self, other: BitStr<'a>) -> BitString {
+        self.extend_from_bit_str(other);
+        self
+    }
+}
+
+impl<'a> Add<&'a BitString> for BitString {
+    type Output = BitString;
+
+    fn add(mut self, other: &'a BitString) -> BitString {
+        self.extend_from_bit_str(other.as_bit_str());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(s: &str) -> BitString {
+        s.chars().map(|c| c == '1').collect()
+    }
+
+    #[test]
+    fn packs_bytes() {
+        let bits = BitString::from_bytes(&[0xa5, 0x0f, 1, 2, 3, 4, 5, 6, 7, 0x80]);
+
+        assert_eq!(bits.len(), 80);
+        assert_eq!(bits.slice(..16).to_string(), "1010010100001111");
+        assert_eq!(bits.slice(72..).to_string(), "10000000");
+        assert_eq!(bits.to_bytes(), vec![0xa5, 0x0f, 1, 2, 3, 4, 5, 6, 7, 0x80]);
+    }
+    #[test]
+    fn slices_across_words() {
+        let mut long = BitString::zeros(60);
+        long.extend(vec![true, false, true, true, false, true, false, false, true]);
+
+        let middle = long.slice(59..68);
+
+        assert_eq!(middle.to_string(), "010110100");
+        assert_eq!(middle.slice(2..5).to_string(), "011");
+        assert!(middle[1]);
+        assert!(!middle[0]);
+        assert_eq!(middle.get(9), None);
+    }
+    #[test]
+    fn concatenates() {
+        let a = bits("101");
+        let b = BitString::from_bytes(&[0xff; 9]);
+
+        let joined = a.clone() + &b;
+
+        assert_eq!(joined.len(), 75);
+        assert_eq!(joined.slice(..3), a);
+        assert_eq!(joined.slice(3..), b);
+        assert_eq!(a.as_bit_str().concat(b.as_bit_str()), joined);
+    }
+    #[test]
+    fn pushes_pops_and_truncates() {
+        let mut bits = BitString::from_bytes(&[0xff; 9]);
+        bits.push(false);
+        assert_eq!(bits.pop(), Some(false));
+        bits.truncate(65);
+        assert_eq!(bits, BitString::from(vec![true; 65]));
+        bits.truncate(64);
+        bits.push(false);
+        assert_eq!(bits.as_bit_str().rposition(true), Some(63));
+    }
+    #[test]
+    fn orders_lexicographically() {
+        assert!(bits("0") < bits("00"));
+        assert!(bits("0011") < bits("01"));
+        assert!(bits("") < bits("0"));
+        assert!(bits("10") > bits("01111"));
+        assert_eq!(bits("0110").slice(1..3), bits("11"));
+    }
+    #[test]
+    fn iterates_both_ways() {
+        let b = bits("1100");
+
+        assert_eq!(b.iter().collect::<Vec<_>>(), vec![true, true, false, false]);
+        assert_eq!(b.iter().rev().collect::<Vec<_>>(), vec![false, false, true, true]);
+    }
+    #[test]
+    fn finds_positions() {
+        let b = BitString::zeros(70) + &bits("1") + &BitString::zeros(3);
+
+        assert_eq!(b.as_bit_str().position(true), Some(70));
+        assert_eq!(b.as_bit_str().rposition(true), Some(70));
+        assert_eq!(b.slice(1..).position(true), Some(69));
+        assert_eq!(b.as_bit_str().rposition(false), Some(73));
+        assert_eq!(bits("111").as_bit_str().position(false), None);
+    }
+    #[test]
+    fn shared_prefix_by_words() {
+        let a = BitString::from_bytes(&[0xaa; 32]);
+        let mut b = a.clone();
+        b.set(200, !b[200]);
+
+        assert_eq!(a.as_bit_str().shared_prefix(b.as_bit_str()).len(), 200);
+        assert_eq!(a.slice(3..).shared_prefix(b.slice(3..)).len(), 197);
+        assert_eq!(a.slice(..100).shared_prefix(b.as_bit_str()).len(), 100);
+        assert_eq!(a.as_bit_str().shared_prefix(a.as_bit_str()).len(), 256);
+        assert_eq!(a.slice(1..).shared_prefix(a.as_bit_str()).len(), 0);
+    }
+    #[test]
+    fn shared_prefix_of_integers() {
+        assert_eq!(shared_prefix_len(7u32, 7u32), 32);
+        assert_eq!(shared_prefix_len(0u64, 1u64), 63);
+        assert_eq!(shared_prefix_len(1u128 << 127, 0u128), 0);
+    }
+    #[test]
+    fn range_prefix_on_unaligned_views() {
+        let padded = bits("111") + &bits("1010100011") + &bits("1010100111");
+        let start = padded.slice(3..13);
+        let end = padded.slice(13..);
+
+        let (a, b) = range_prefix(start, end);
+
+        assert_eq!(a, bits("1010100011"));
+        assert_eq!(b, bits("10101001"));
+    }
+    #[test]
+    fn cover_across_words() {
+        let start = BitString::zeros(100) + &bits("01");
+        let end = BitString::zeros(99) + &bits("100");
+
+        let cover = range_cover(start.as_bit_str(), end.as_bit_str()).unwrap();
+
+        assert_eq!(cover, vec![
+            BitString::zeros(100) + &bits("01"),
+            BitString::zeros(100) + &bits("1"),
+            BitString::zeros(99) + &bits("100"),
+        ]);
+    }
+}
@@ -0,0 +1,232 @@
+//! Covers for open, half-open and unbounded ranges.
+//!
+//! [`cover`] accepts any `RangeBounds`, so `a..b`, `a..=b`, `a..`, `..b`, `..=b` and `..`
+//! all work. Exclusive bounds are moved to the next key inside the range and unbounded
+//! sides extend to the smallest or largest key.
+//!
+//! A range that holds no keys, such as `5..5`, has an empty cover. A range whose start
+//! is greater than its end, such as `6..5`, is rejected with
+//! [`RangePrefixError::InvertedRange`].
+
+use std::ops::{Bound, RangeBounds};
+
+use bits::BitString;
+use error::RangePrefixError;
+use float_key::FloatKey;
+use int_key::{self, IntKey};
+use lex::{self, LexPrefix};
+
+/// Keys whose ranges can be covered with prefixes.
+pub trait CoverKey: Sized {
+    /// The type of the prefixes in a cover.
+    type Prefix;
+
+    /// Finds the minimal set of prefixes covering the keys in `range`.
+    fn cover<R: RangeBounds<Self>>(range: R) -> Result<Vec<Self::Prefix>, RangePrefixError>;
+}
+
+/// Finds the minimal set of prefixes covering the keys in `range`.
+///
+/// Integers and floats are covered with [`BitString`] prefixes of their fixed-width
+/// encodings. Byte strings and string slices are covered in lexicographic order with
+/// [`LexPrefix`] entries.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::cover;
+///
+/// cover(250u8..);
+/// // Ok([1111101, 111111])
+/// cover(..=3i8);
+/// // Ok([0, 100000])
+/// cover("a".."b");
+/// // Ok([Prefix("a")])
+/// ```
+pub fn cover<K: CoverKey, R: RangeBounds<K>>(range: R) -> Result<Vec<K::Prefix>, RangePrefixError> {
+    K::cover(range)
+}
+
+/// Returns `true` if the start bound is greater than the end bound.
+fn inverted<K: ?Sized, R: RangeBounds<K>, F: Fn(&K, &K) -> bool>(range: &R, greater: F) -> bool {
+    match (range.start_bound(), range.end_bound()) {
+        (Bound::Included(a) | Bound::Excluded(a), Bound::Included(b) | Bound::Excluded(b)) => greater(a, b),
+        _ => false,
+    }
+}
+
+macro_rules! int_cover_key {
+    ($($ty:ty),*) => {$(
+        impl CoverKey for $ty {
+            type Prefix = BitString;
+
+            fn cover<R: RangeBounds<$ty>>(range: R) -> Result<Vec<BitString>, RangePrefixError> {
+                if inverted(&range, |a, b| a > b) {
+                    return Err(RangePrefixError::InvertedRange);
+                }
+                let lo = match range.start_bound() {
+                    Bound::Included(&lo) => Some(lo),
+                    Bound::Excluded(&lo) => lo.checked_add(1),
+                    Bound::Unbounded => Some(<$ty>::MIN),
+                };
+                let hi = match range.end_bound() {
+                    Bound::Included(&hi) => Some(hi),
+                    Bound::Excluded(&hi) => hi.checked_sub(1),
+                    Bound::Unbounded => Some(<$ty>::MAX),
+                };
+                match (lo, hi) {
+                    (Some(lo), Some(hi)) if lo <= hi => int_key::range_cover(lo, hi),
+                    _ => Ok(Vec::new()),
+                }
+            }
+        }
+    )*};
+}
+
+int_cover_key!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+macro_rules! float_cover_key {
+    ($($ty:ty => $bits:ty),*) => {$(
+        impl CoverKey for $ty {
+            type Prefix = BitString;
+
+            /// Floats are ordered by the IEEE-754 total order described in
+            /// [`float_key`](../float_key/index.html).
+            fn cover<R: RangeBounds<$ty>>(range: R) -> Result<Vec<BitString>, RangePrefixError> {
+                // the float keys are the integer keys of the ordered bits
+                let ordered = |bound: Bound<&$ty>| match bound {
+                    Bound::Included(v) => Bound::Included(<$bits>::from_key(v.to_key().as_bit_str()).unwrap()),
+                    Bound::Excluded(v) => Bound::Excluded(<$bits>::from_key(v.to_key().as_bit_str()).unwrap()),
+                    Bound::Unbounded => Bound::Unbounded,
+                };
+                <$bits>::cover((ordered(range.start_bound()), ordered(range.end_bound())))
+            }
+        }
+    )*};
+}
+
+float_cover_key!(f32 => u32, f64 => u64);
+
+impl<'a> CoverKey for &'a [u8] {
+    type Prefix = LexPrefix;
+
+    fn cover<R: RangeBounds<&'a [u8]>>(range: R) -> Result<Vec<LexPrefix>, RangePrefixError> {
+        lex::bounds_cover(bytes(range.start_bound(), |k| *k), bytes(range.end_bound(), |k| *k))
+    }
+}
+
+impl CoverKey for Vec<u8> {
+    type Prefix = LexPrefix;
+
+    fn cover<R: RangeBounds<Vec<u8>>>(range: R) -> Result<Vec<LexPrefix>, RangePrefixError> {
+        lex::bounds_cover(bytes(range.start_bound(), |k| &k[..]), bytes(range.end_bound(), |k| &k[..]))
+    }
+}
+
+impl<'a> CoverKey for &'a str {
+    type Prefix = LexPrefix;
+
+    fn cover<R: RangeBounds<&'a str>>(range: R) -> Result<Vec<LexPrefix>, RangePrefixError> {
+        lex::bounds_cover(bytes(range.start_bound(), |k| k.as_bytes()), bytes(range.end_bound(), |k| k.as_bytes()))
+    }
+}
+
+impl CoverKey for String {
+    type Prefix = LexPrefix;
+
+    fn cover<R: RangeBounds<String>>(range: R) -> Result<Vec<LexPrefix>, RangePrefixError> {
+        lex::bounds_cover(bytes(range.start_bound(), |k| k.as_bytes()), bytes(range.end_bound(), |k| k.as_bytes()))
+    }
+}
+
+fn bytes<'a, K, F: Fn(&'a K) -> &'a [u8]>(bound: Bound<&'a K>, as_bytes: F) -> Bound<&'a [u8]> {
+    match bound {
+        Bound::Included(key) => Bound::Included(as_bytes(key)),
+        Bound::Excluded(key) => Bound::Excluded(as_bytes(key)),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type ByteRange<'a> = (Bound<&'a [u8]>, Bound<&'a [u8]>);
+
+    fn check(cover: Vec<BitString>, expected: fn(u8) -> bool) {
+        for key in 0..=255u8 {
+            let key_bits = key.to_key();
+            let covered = cover.iter().any(|p| key_bits.as_bit_str().starts_with(p.as_bit_str()));
+            assert_eq!(covered, expected(key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn covers_every_kind_of_integer_range() {
+        check(cover(10u8..20).unwrap(), |k| (10..20).contains(&k));
+        check(cover(10u8..=20).unwrap(), |k| (10..=20).contains(&k));
+        check(cover(10u8..).unwrap(), |k| k >= 10);
+        check(cover(..20u8).unwrap(), |k| k < 20);
+        check(cover(..=20u8).unwrap(), |k| k <= 20);
+        check(cover::<u8, _>(..).unwrap(), |_| true);
+        check(cover((Bound::Excluded(10u8), Bound::Included(20))).unwrap(), |k| k > 10 && k <= 20);
+    }
+    #[test]
+    fn matches_documented_covers() {
+        let bits = |s: &str| s.chars().map(|c| c == '1').collect::<BitString>();
+
+        assert_eq!(cover(250u8..), Ok(vec![bits("1111101"), bits("111111")]));
+        assert_eq!(cover(..=3i8), Ok(vec![bits("0"), bits("100000")]));
+        assert_eq!(cover("a".."b"), Ok(vec![LexPrefix::Prefix(b"a".to_vec())]));
+    }
+    #[test]
+    fn empty_and_inverted_ranges() {
+        assert_eq!(cover(5u32..5), Ok(vec![]));
+        assert_eq!(cover(..0u32), Ok(vec![]));
+        assert_eq!(cover((Bound::Excluded(255u8), Bound::Unbounded)), Ok(vec![]));
+        assert_eq!(cover((Bound::Included(6i64), Bound::Excluded(5))), Err(RangePrefixError::InvertedRange));
+        assert_eq!(cover((Bound::Included(6i64), Bound::Included(5))), Err(RangePrefixError::InvertedRange));
+        assert_eq!(cover("b".."a"), Err(RangePrefixError::InvertedRange));
+        assert_eq!(cover("a".."a"), Ok(vec![]));
+    }
+    #[test]
+    fn covers_float_ranges() {
+        let below = cover(..0.0f64).unwrap();
+        let inside = |v: f64| {
+            let key = v.to_key();
+            below.iter().any(|p| key.as_bit_str().starts_with(p.as_bit_str()))
+        };
+
+        assert!(inside(-1.0));
+        assert!(inside(-0.0));
+        assert!(!inside(0.0));
+        assert!(!inside(1.0));
+        assert_eq!(cover(1.0f32..).unwrap(), cover(1.0f32..=f32::from_bits(0x7fff_ffff)).unwrap());
+    }
+    #[test]
+    fn covers_byte_string_ranges() {
+        let keys: Vec<&[u8]> = vec![b"", b"a", b"a\0", b"ab", b"abc", b"abd", b"b", b"b\xff"];
+        let ranges: Vec<ByteRange> = vec![
+            (Bound::Excluded(b"a"), Bound::Unbounded),
+            (Bound::Included(b"ab"), Bound::Excluded(b"abd")),
+            (Bound::Unbounded, Bound::Excluded(b"ab")),
+            (Bound::Unbounded, Bound::Included(b"ab")),
+            (Bound::Excluded(b"a"), Bound::Excluded(b"b")),
+            (Bound::Unbounded, Bound::Unbounded),
+        ];
+        for range in ranges {
+            let cover = cover::<&[u8], _>(range).unwrap();
+            for key in &keys {
+                let hits = cover.iter().filter(|entry| entry.matches(key)).count();
+                let expected = if range.contains(key) { 1 } else { 0 };
+                assert_eq!(hits, expected, "{:?} in {:?}", key, range);
+            }
+        }
+    }
+    #[test]
+    fn covers_string_ranges() {
+        assert_eq!(cover("cursor".to_string()..), cover(&b"cursor"[..]..));
+        assert_eq!(cover(..=b"ab".to_vec()), cover(..="ab"));
+        assert_eq!(cover::<&str, _>(..), Ok(vec![LexPrefix::Prefix(vec![])]));
+    }
+}
@@ -0,0 +1,131 @@
+//! Prefix covers made of whole bytes.
+//!
+//! Redis and S3 match prefixes a byte at a time, so a bit prefix that ends in the
+//! middle of a byte cannot be issued directly. Each such prefix is expanded into the
+//! byte values it matches: a prefix that fixes `r` bits of its last byte becomes
+//! `2^(8 - r)` byte prefixes.
+//!
+//! Expanding the minimal bit cover gives the minimal byte cover, because a complete set of
+//! 256 sibling bytes would already have been merged into their parent by the bit cover.
+
+use bits::{self, BitStr, BitString};
+use error::RangePrefixError;
+
+/// A prefix from the bit cover and the whole-byte prefixes it expands to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ByteExpansion {
+    /// The prefix from the exact bit cover.
+    pub bit_prefix: BitString,
+    /// The byte prefixes matching the same keys, in ascending order.
+    pub byte_prefixes: Vec<Vec<u8>>,
+}
+
+impl ByteExpansion {
+    /// Expands a bit prefix into whole-byte prefixes.
+    pub fn new(bit_prefix: BitStr) -> ByteExpansion {
+        let whole = bit_prefix.len() / 8 * 8;
+        let head = bit_prefix.slice(..whole).to_bytes();
+        let fixed = bit_prefix.len() - whole;
+
+        let byte_prefixes = if fixed == 0 {
+            vec![head]
+        } else {
+            let first = bit_prefix.slice(whole..).to_bytes()[0];
+            let count = 1u16 << (8 - fixed);
+            (0..count)
+                .map(|i| {
+                    let mut prefix = head.clone();
+                    prefix.push(first | i as u8);
+                    prefix
+                })
+                .collect()
+        };
+
+        ByteExpansion {
+            bit_prefix: bit_prefix.to_bit_string(),
+            byte_prefixes,
+        }
+    }
+
+    /// Returns the number of byte prefixes, i.e. the number of scans this part of the cover costs.
+    pub fn cost(&self) -> usize {
+        self.byte_prefixes.len()
+    }
+}
+
+/// Finds the minimal set of whole-byte prefixes covering the keys in `[lo, hi]`.
+///
+/// The keys must have the same length, which errors report in bytes. Each entry of the result is one prefix of the
+/// exact bit cover along with its byte expansion.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::byte_key::range_cover;
+///
+/// let cover = range_cover(b"a0", b"a9").unwrap();
+///
+/// cover.iter().map(|e| e.cost()).collect::<Vec<_>>();
+/// // [8, 2]
+/// cover.iter().flat_map(|e| e.byte_prefixes.iter()).count();
+/// // 10
+/// ```
+pub fn range_cover(lo: &[u8], hi: &[u8]) -> Result<Vec<ByteExpansion>, RangePrefixError> {
+    let lo_bits = BitString::from_bytes(lo);
+    let hi_bits = BitString::from_bytes(hi);
+
+    let cover = bits::range_cover(lo_bits.as_bit_str(), hi_bits.as_bit_str()).map_err(|err| match err {
+        RangePrefixError::LengthMismatch { .. } => RangePrefixError::LengthMismatch {
+            start: lo.len(),
+            end: hi.len(),
+        },
+        err => err,
+    })?;
+
+    Ok(cover.iter().map(|prefix| ByteExpansion::new(prefix.as_bit_str())).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefixes(cover: &[ByteExpansion]) -> Vec<Vec<u8>> {
+        cover.iter().flat_map(|e| e.byte_prefixes.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn expands_partial_bytes() {
+        let bits: BitString = "0110000".chars().map(|c| c == '1').collect();
+
+        let expansion = ByteExpansion::new(bits.as_bit_str());
+
+        assert_eq!(expansion.byte_prefixes, vec![vec![0x60], vec![0x61]]);
+        assert_eq!(expansion.cost(), 2);
+        assert_eq!(ByteExpansion::new(BitString::new().as_bit_str()).byte_prefixes, vec![vec![]]);
+    }
+    #[test]
+    fn covers_byte_ranges_exactly() {
+        for &(lo, hi) in &[([0x00, 0x10], [0x03, 0x7f]), ([0x41, 0xff], [0x42, 0x00]), ([7, 7], [7, 7])] {
+            let cover = prefixes(&range_cover(&lo, &hi).unwrap());
+            for key in 0..=0xffffu16 {
+                let key = key.to_be_bytes();
+                let hits = cover.iter().filter(|p| key.starts_with(p)).count();
+                let expected = if lo <= key && key <= hi { 1 } else { 0 };
+                assert_eq!(hits, expected, "{:?} in {:?}..={:?}", key, lo, hi);
+            }
+            assert!(cover.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+    #[test]
+    fn whole_byte_ranges_need_no_expansion() {
+        let cover = range_cover(b"user:\x00\x00", b"user:\xff\xff").unwrap();
+
+        assert_eq!(prefixes(&cover), vec![b"user:".to_vec()]);
+        assert_eq!(cover[0].cost(), 1);
+    }
+    #[test]
+    fn rejects_malformed_ranges() {
+        assert_eq!(range_cover(b"b", b"a"), Err(RangePrefixError::InvertedRange));
+        assert_eq!(range_cover(b"ab", b"b"), Err(RangePrefixError::LengthMismatch { start: 2, end: 1 }));
+    }
+}
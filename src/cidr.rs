@@ -0,0 +1,235 @@
+//! Conversion between address ranges and CIDR blocks.
+//!
+//! A CIDR block is a bit prefix of an address, so the minimal list of blocks covering
+//! an address range is the prefix cover of that range.
+
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use bits::{BitStr, BitString};
+use error::{NetError, RangePrefixError};
+use int_key::{self, IntKey};
+
+macro_rules! ip_net {
+    (
+        $(#[$attr:meta])*
+        $net:ident, $addr:ident, $int:ty, $range_to_cidrs:ident, $cidr_to_range:ident
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $net {
+            addr: $addr,
+            prefix_len: u8,
+        }
+
+        impl $net {
+            /// The number of bits in an address.
+            pub const BITS: u8 = <$int>::BITS as u8;
+
+            /// Creates the block of addresses sharing the first `prefix_len` bits of `addr`.
+            ///
+            /// Host bits of `addr` are cleared, so the stored address is always the first in the block.
+            pub fn new(addr: $addr, prefix_len: u8) -> Result<$net, NetError> {
+                if prefix_len > Self::BITS {
+                    return Err(NetError::InvalidPrefixLength(prefix_len));
+                }
+                let addr = <$int>::from(addr) & Self::netmask_bits(prefix_len);
+                Ok($net {
+                    addr: $addr::from(addr),
+                    prefix_len,
+                })
+            }
+
+            /// Returns the first address in the block.
+            pub fn addr(&self) -> $addr {
+                self.addr
+            }
+
+            /// Returns the number of fixed leading bits.
+            pub fn prefix_len(&self) -> u8 {
+                self.prefix_len
+            }
+
+            /// Returns the last address in the block.
+            pub fn last(&self) -> $addr {
+                $addr::from(<$int>::from(self.addr) | !Self::netmask_bits(self.prefix_len))
+            }
+
+            /// Returns the first and last addresses in the block.
+            pub fn range(&self) -> ($addr, $addr) {
+                (self.addr, self.last())
+            }
+
+            /// Returns `true` if `addr` is inside the block.
+            pub fn contains(&self, addr: $addr) -> bool {
+                <$int>::from(addr) & Self::netmask_bits(self.prefix_len) == <$int>::from(self.addr)
+            }
+
+            /// Returns the block as a bit prefix.
+            pub fn to_prefix(&self) -> BitString {
+                <$int>::from(self.addr).to_key().slice(..usize::from(self.prefix_len)).to_bit_string()
+            }
+
+            /// Creates a block from a bit prefix, or `None` if the prefix is longer than an address.
+            pub fn from_prefix(prefix: BitStr) -> Option<$net> {
+                let width = usize::from(Self::BITS);
+                if prefix.len() > width {
+                    return None;
+                }
+                let addr = prefix.concat(BitString::zeros(width - prefix.len()).as_bit_str());
+                Some($net {
+                    addr: $addr::from(<$int>::from_key(addr.as_bit_str())?),
+                    prefix_len: prefix.len() as u8,
+                })
+            }
+
+            fn netmask_bits(prefix_len: u8) -> $int {
+                (!0 as $int).checked_shl(u32::from(Self::BITS - prefix_len)).unwrap_or(0)
+            }
+        }
+
+        impl fmt::Display for $net {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}/{}", self.addr, self.prefix_len)
+            }
+        }
+
+        impl FromStr for $net {
+            type Err = NetError;
+
+            fn from_str(s: &str) -> Result<$net, NetError> {
+                let mut parts = s.splitn(2, '/');
+                let addr = parts.next().and_then(|a| a.parse().ok()).ok_or(NetError::InvalidSyntax)?;
+                let prefix_len = match parts.next() {
+                    Some(len) => len.parse().map_err(|_| NetError::InvalidSyntax)?,
+                    None => Self::BITS,
+                };
+                $net::new(addr, prefix_len)
+            }
+        }
+
+        impl From<$net> for BitString {
+            fn from(net: $net) -> BitString {
+                net.to_prefix()
+            }
+        }
+
+        /// Finds the minimal list of CIDR blocks covering the addresses in `[start, end]`.
+        pub fn $range_to_cidrs(start: $addr, end: $addr) -> Result<Vec<$net>, RangePrefixError> {
+            let cover = int_key::range_cover(<$int>::from(start), <$int>::from(end))?;
+            Ok(cover
+                .iter()
+                .filter_map(|prefix| $net::from_prefix(prefix.as_bit_str()))
+                .collect())
+        }
+
+        /// Returns the first and last addresses of a CIDR block.
+        pub fn $cidr_to_range(net: $net) -> ($addr, $addr) {
+            net.range()
+        }
+    };
+}
+
+ip_net! {
+    /// An IPv4 CIDR block, such as `10.0.0.0/8`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use binary_prefix::cidr::{ipv4_range_to_cidrs, Ipv4Net};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let nets = ipv4_range_to_cidrs(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 6)).unwrap();
+    ///
+    /// nets.iter().map(Ipv4Net::to_string).collect::<Vec<_>>();
+    /// // ["10.0.0.1/32", "10.0.0.2/31", "10.0.0.4/31", "10.0.0.6/32"]
+    /// ```
+    Ipv4Net, Ipv4Addr, u32, ipv4_range_to_cidrs, ipv4_cidr_to_range
+}
+
+ip_net! {
+    /// An IPv6 CIDR block, such as `2001:db8::/32`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use binary_prefix::cidr::{ipv6_range_to_cidrs, Ipv6Net};
+    ///
+    /// let nets = ipv6_range_to_cidrs("2001:db8::".parse().unwrap(), "2001:db8::1:ffff".parse().unwrap()).unwrap();
+    ///
+    /// nets.iter().map(Ipv6Net::to_string).collect::<Vec<_>>();
+    /// // ["2001:db8::/111"]
+    /// ```
+    Ipv6Net, Ipv6Addr, u128, ipv6_range_to_cidrs, ipv6_cidr_to_range
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v4(nets: &[&str]) -> Vec<Ipv4Net> {
+        nets.iter().map(|n| n.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn converts_ipv4_range() {
+        let nets = ipv4_range_to_cidrs(Ipv4Addr::new(192, 168, 0, 10), Ipv4Addr::new(192, 168, 1, 255)).unwrap();
+
+        assert_eq!(nets, v4(&[
+            "192.168.0.10/31",
+            "192.168.0.12/30",
+            "192.168.0.16/28",
+            "192.168.0.32/27",
+            "192.168.0.64/26",
+            "192.168.0.128/25",
+            "192.168.1.0/24",
+        ]));
+    }
+    #[test]
+    fn converts_whole_address_space() {
+        let nets = ipv4_range_to_cidrs(Ipv4Addr::new(0, 0, 0, 0), Ipv4Addr::new(255, 255, 255, 255)).unwrap();
+
+        assert_eq!(nets, v4(&["0.0.0.0/0"]));
+        assert_eq!(ipv4_cidr_to_range(nets[0]), (Ipv4Addr::new(0, 0, 0, 0), Ipv4Addr::new(255, 255, 255, 255)));
+    }
+    #[test]
+    fn ranges_round_trip() {
+        let start: Ipv6Addr = "fe80::3".parse().unwrap();
+        let end: Ipv6Addr = "fe80::1:0:0:7".parse().unwrap();
+
+        let nets = ipv6_range_to_cidrs(start, end).unwrap();
+
+        assert_eq!(nets.first().unwrap().addr(), start);
+        assert_eq!(nets.last().unwrap().last(), end);
+        for pair in nets.windows(2) {
+            assert_eq!(u128::from(pair[0].last()) + 1, u128::from(pair[1].addr()));
+        }
+    }
+    #[test]
+    fn parses_and_masks() {
+        let net: Ipv4Net = "10.1.2.3/8".parse().unwrap();
+
+        assert_eq!(net.to_string(), "10.0.0.0/8");
+        assert!(net.contains(Ipv4Addr::new(10, 255, 0, 1)));
+        assert!(!net.contains(Ipv4Addr::new(11, 0, 0, 0)));
+        assert_eq!("10.0.0.1".parse::<Ipv4Net>().unwrap().prefix_len(), 32);
+        assert_eq!("10.0.0.0/33".parse::<Ipv4Net>(), Err(NetError::InvalidPrefixLength(33)));
+        assert_eq!("10.0.0/8".parse::<Ipv4Net>(), Err(NetError::InvalidSyntax));
+        assert_eq!("::/129".parse::<Ipv6Net>(), Err(NetError::InvalidPrefixLength(129)));
+    }
+    #[test]
+    fn converts_to_prefixes() {
+        let net: Ipv4Net = "128.0.0.0/3".parse().unwrap();
+
+        assert_eq!(net.to_prefix().to_string(), "100");
+        assert_eq!(Ipv4Net::from_prefix(net.to_prefix().as_bit_str()), Some(net));
+        assert_eq!(Ipv4Net::from_prefix(BitString::zeros(33).as_bit_str()), None);
+    }
+    #[test]
+    fn rejects_inverted_range() {
+        let result = ipv4_range_to_cidrs(Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 1));
+
+        assert_eq!(result, Err(RangePrefixError::InvertedRange));
+    }
+}
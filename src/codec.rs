@@ -0,0 +1,237 @@
+//! Helpers for the crate's compact binary formats.
+//!
+//! Integers are written as LEB128 varints and bit strings and byte strings are
+//! length-prefixed. Signed formats end with a SipHash-2-4 MAC of everything before it.
+
+use bits::{BitStr, BitString};
+use error::DecodeError;
+
+/// Appends values to a byte buffer.
+pub(crate) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new(magic: &[u8], version: u8) -> Writer {
+        let mut buf = magic.to_vec();
+        buf.push(version);
+        Writer { buf }
+    }
+
+    pub fn u64(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    pub fn bits(&mut self, bits: BitStr) {
+        self.u64(bits.len() as u64);
+        self.buf.extend_from_slice(&bits.to_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// Appends a MAC of the buffer under `key`.
+    pub fn sign(mut self, key: [u64; 2]) -> Vec<u8> {
+        let mac = siphash(key, &self.buf);
+        self.buf.extend_from_slice(&mac.to_le_bytes());
+        self.buf
+    }
+}
+
+/// Reads values back from a byte buffer.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the magic bytes and returns the reader along with the format version.
+    pub fn new(buf: &'a [u8], magic: &[u8]) -> Result<(Reader<'a>, u8), DecodeError> {
+        if !buf.starts_with(magic) {
+            return Err(DecodeError::UnknownFormat);
+        }
+        let mut reader = Reader {
+            buf: &buf[magic.len()..],
+        };
+        let version = reader.take(1)?[0];
+        Ok((reader, version))
+    }
+
+    /// Checks the MAC at the end of the buffer under `key` and stops reading before it.
+    ///
+    /// `signed` is the whole buffer the reader was created from.
+    pub fn verify(&mut self, signed: &[u8], key: [u64; 2]) -> Result<(), DecodeError> {
+        if self.buf.len() < 8 {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (body, mac) = signed.split_at(signed.len() - 8);
+        // compared in constant time, so the time taken doesn't reveal how much of a forged MAC is right
+        let diff = siphash(key, body).to_le_bytes().iter().zip(mac).fold(0, |diff, (a, b)| diff | (a ^ b));
+        if diff != 0 {
+            return Err(DecodeError::InvalidSignature);
+        }
+        self.buf = &self.buf[..self.buf.len() - 8];
+        Ok(())
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            // only the lowest bit of the tenth byte fits
+            if shift == 63 && byte & 0x7f > 1 {
+                return Err(DecodeError::Corrupt);
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::Corrupt)
+    }
+
+    pub fn usize(&mut self) -> Result<usize, DecodeError> {
+        let value = self.u64()?;
+        if value > usize::MAX as u64 {
+            return Err(DecodeError::Corrupt);
+        }
+        Ok(value as usize)
+    }
+
+    pub fn bits(&mut self) -> Result<BitString, DecodeError> {
+        let len = self.usize()?;
+        let bytes = self.take(len.div_ceil(8))?;
+        let mut bits = BitString::from_bytes(bytes);
+        bits.truncate(len);
+        Ok(bits)
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = self.usize()?;
+        self.take(len)
+    }
+
+    /// Fails unless every byte has been read.
+    pub fn finish(self) -> Result<(), DecodeError> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(DecodeError::Corrupt)
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.buf.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+}
+
+/// Writes bytes as lowercase hexadecimal.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Reads bytes back from hexadecimal.
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    hex.as_bytes().chunks(2).map(|pair| Some(digit(pair[0])? << 4 | digit(pair[1])?)).collect()
+}
+
+/// Computes SipHash-2-4 of `data` under a 128-bit key.
+pub(crate) fn siphash(key: [u64; 2], data: &[u8]) -> u64 {
+    let mut v = [
+        key[0] ^ 0x736f_6d65_7073_6575,
+        key[1] ^ 0x646f_7261_6e64_6f6d,
+        key[0] ^ 0x6c79_6765_6e65_7261,
+        key[1] ^ 0x7465_6462_7974_6573,
+    ];
+    let compress = |v: &mut [u64; 4], m: u64| {
+        v[3] ^= m;
+        sip_round(v);
+        sip_round(v);
+        v[0] ^= m;
+    };
+
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut word = [0; 8];
+        word.copy_from_slice(chunk);
+        compress(&mut v, u64::from_le_bytes(word));
+    }
+    // the last word holds the leftover bytes and the length
+    let mut last = [0; 8];
+    last[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+    last[7] = data.len() as u8;
+    compress(&mut v, u64::from_le_bytes(last));
+
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        sip_round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(13) ^ v[0];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(16) ^ v[2];
+    v[0] = v[0].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(21) ^ v[0];
+    v[2] = v[2].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(17) ^ v[2];
+    v[2] = v[2].rotate_left(32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_siphash_reference() {
+        let key = [0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908];
+        let data: Vec<u8> = (0..15).collect();
+
+        assert_eq!(siphash(key, &data), 0xa129_ca61_49be_45e5);
+        assert_eq!(siphash(key, &[]), 0x726f_db47_dd0e_0e31);
+    }
+    #[test]
+    fn rejects_overlong_varints() {
+        let read = |bytes: &[u8]| {
+            let mut buf = b"T\x01".to_vec();
+            buf.extend_from_slice(bytes);
+            Reader::new(&buf, b"T").unwrap().0.u64()
+        };
+        let mut max = Writer::new(b"T", 1);
+        max.u64(u64::MAX);
+
+        assert_eq!(read(&max.finish()[2..]), Ok(u64::MAX));
+        assert_eq!(read(b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02"), Err(DecodeError::Corrupt));
+        assert_eq!(read(b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x81\x00"), Err(DecodeError::Corrupt));
+    }
+    #[test]
+    fn round_trips_hex() {
+        assert_eq!(to_hex(b"\x00a\xff"), "0061ff");
+        assert_eq!(from_hex("0061FF"), Some(b"\x00a\xff".to_vec()));
+        assert_eq!(from_hex("006"), None);
+        assert_eq!(from_hex("0g"), None);
+        assert_eq!(from_hex("x\u{e9}x"), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! Error types.
+
+use std::error::Error;
+use std::fmt;
+
+use bits::BitStr;
+
+/// The reasons a range of keys can be rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangePrefixError {
+    /// The two bounds have different lengths.
+    LengthMismatch {
+        /// The length of the start of the range.
+        start: usize,
+        /// The length of the end of the range.
+        end: usize,
+    },
+    /// The start of the range is greater than its end.
+    InvertedRange,
+    /// One of the bounds has no bits.
+    EmptyInput,
+    /// A bound contains a symbol that is not in the alphabet.
+    InvalidSymbol(char),
+}
+
+impl fmt::Display for RangePrefixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RangePrefixError::LengthMismatch { start, end } => write!(
+                f,
+                "range bounds have different lengths (start is {}, end is {})",
+                start, end
+            ),
+            RangePrefixError::InvertedRange => f.write_str("range start is greater than range end"),
+            RangePrefixError::EmptyInput => f.write_str("range bounds must not be empty"),
+            RangePrefixError::InvalidSymbol(symbol) => write!(f, "range bound contains {:?}, which is not in the alphabet", symbol),
+        }
+    }
+}
+
+impl Error for RangePrefixError {}
+
+/// Keys of a fixed width, made of bits or digits, whose ranges [`validate_range`] checks.
+pub(crate) trait FixedWidth: PartialOrd {
+    /// Returns the number of bits or digits.
+    fn width(&self) -> usize;
+}
+
+impl<'a> FixedWidth for BitStr<'a> {
+    fn width(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: PartialOrd> FixedWidth for [T] {
+    fn width(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Checks that `[start, end]` is a well-formed range of fixed-width keys.
+pub(crate) fn validate_range<K: FixedWidth + ?Sized>(start: &K, end: &K) -> Result<(), RangePrefixError> {
+    if start.width() == 0 || end.width() == 0 {
+        return Err(RangePrefixError::EmptyInput);
+    }
+    if start.width() != end.width() {
+        return Err(RangePrefixError::LengthMismatch {
+            start: start.width(),
+            end: end.width(),
+        });
+    }
+    if start > end {
+        return Err(RangePrefixError::InvertedRange);
+    }
+    Ok(())
+}
+
+/// The reasons a CIDR block can be rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetError {
+    /// The prefix length is longer than the address.
+    InvalidPrefixLength(u8),
+    /// The text is not of the form `address/length`.
+    InvalidSyntax,
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NetError::InvalidPrefixLength(len) => write!(f, "prefix length {} is longer than the address", len),
+            NetError::InvalidSyntax => f.write_str("invalid CIDR syntax, expected `address/length`"),
+        }
+    }
+}
+
+impl Error for NetError {}
+
+/// The reasons an alphabet can be rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphabetError {
+    /// The alphabet has fewer than two symbols.
+    TooShort,
+    /// A symbol appears more than once.
+    DuplicateSymbol(char),
+}
+
+impl fmt::Display for AlphabetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AlphabetError::TooShort => f.write_str("an alphabet needs at least two symbols"),
+            AlphabetError::DuplicateSymbol(symbol) => write!(f, "symbol {:?} appears more than once", symbol),
+        }
+    }
+}
+
+impl Error for AlphabetError {}
+
+/// The reasons serialized data can be rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data does not start with the expected magic bytes.
+    UnknownFormat,
+    /// The data was written by a newer or unknown version of the format.
+    UnsupportedVersion(u8),
+    /// The data ends in the middle of a value.
+    UnexpectedEnd,
+    /// The data is well-framed but its contents are inconsistent.
+    Corrupt,
+    /// The data was altered, or signed with a different key.
+    InvalidSignature,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnknownFormat => f.write_str("data is not in the expected format"),
+            DecodeError::UnsupportedVersion(version) => write!(f, "format version {} is not supported", version),
+            DecodeError::UnexpectedEnd => f.write_str("data ends unexpectedly"),
+            DecodeError::Corrupt => f.write_str("data is corrupt"),
+            DecodeError::InvalidSignature => f.write_str("data signature does not match"),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// The reasons scan results cannot be merged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeError {
+    /// Sorting the results would hold more than `limit` keys in memory.
+    BufferFull {
+        /// The most keys that may be held.
+        limit: usize,
+    },
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MergeError::BufferFull { limit } => write!(f, "more than {} keys would be buffered", limit),
+        }
+    }
+}
+
+impl Error for MergeError {}
+
+/// The reasons a store rejects a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StoreError {
+    /// The continuation token was not issued by the store.
+    InvalidContinuationToken,
+    /// The continuation token was issued for a request with another prefix or `StartAfter`.
+    ContinuationTokenMismatch,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StoreError::InvalidContinuationToken => f.write_str("the continuation token is not valid"),
+            StoreError::ContinuationTokenMismatch => f.write_str("the continuation token belongs to a different request"),
+        }
+    }
+}
+
+impl Error for StoreError {}
+
+/// The reasons a range query against a store can fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryError<E> {
+    /// The range was rejected before the store was queried.
+    Range(RangePrefixError),
+    /// The store failed to list keys.
+    Store(E),
+    /// The cursor was issued for a different range or kind of store.
+    CursorMismatch,
+}
+
+impl<E: fmt::Display> fmt::Display for QueryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryError::Range(ref error) => write!(f, "invalid range: {}", error),
+            QueryError::Store(ref error) => write!(f, "store failed to list keys: {}", error),
+            QueryError::CursorMismatch => f.write_str("cursor does not belong to this range"),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for QueryError<E> {}
@@ -0,0 +1,158 @@
+//! Trimming scan results back to the exact range.
+//!
+//! Prefix scans can return keys outside the range, either because a
+//! [planned](../planner/index.html) cover merged prefixes or because the range was
+//! covered by a single [`range_prefix`](../fn.range_prefix.html). A [`RangeFilter`]
+//! drops those keys and counts them, so over-fetch can be monitored.
+
+use std::borrow::Borrow;
+use std::ops::{Bound, RangeBounds};
+
+/// An iterator adapter that yields only the keys within a range.
+///
+/// Keys are compared with `Ord`, which is the scan order for byte strings, `BitString`s
+/// and the encodings in [`int_key`](../int_key/index.html). Any item that can be borrowed
+/// as the key type works, so a range over `Vec<u8>` filters an iterator of `Vec<u8>`.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::filter::RangeFilter;
+///
+/// let scanned = vec![b"user:07".to_vec(), b"user:10".to_vec(), b"user:19".to_vec()];
+/// let mut filter = RangeFilter::new(scanned.into_iter(), b"user:10".to_vec()..);
+///
+/// filter.by_ref().count();
+/// // 2
+/// filter.discarded();
+/// // 1
+/// ```
+#[derive(Clone, Debug)]
+pub struct RangeFilter<I, K> {
+    iter: I,
+    start: Bound<K>,
+    end: Bound<K>,
+    kept: u64,
+    discarded: u64,
+}
+
+impl<I, K: Ord + Clone> RangeFilter<I, K> {
+    /// Wraps `iter`, keeping only the keys within `range`.
+    pub fn new<R: RangeBounds<K>>(iter: I, range: R) -> RangeFilter<I, K> {
+        RangeFilter {
+            iter,
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+            kept: 0,
+            discarded: 0,
+        }
+    }
+}
+
+impl<I, K: Ord> RangeFilter<I, K> {
+    /// Returns `true` if `key` is within the range.
+    pub fn contains(&self, key: &K) -> bool {
+        (self.start.as_ref(), self.end.as_ref()).contains(key)
+    }
+
+    /// Returns the number of keys yielded so far.
+    pub fn kept(&self) -> u64 {
+        self.kept
+    }
+
+    /// Returns the number of keys dropped so far for being outside the range.
+    pub fn discarded(&self) -> u64 {
+        self.discarded
+    }
+
+    /// Returns the fraction of the keys seen so far that were outside the range.
+    pub fn overfetch(&self) -> f64 {
+        let seen = self.kept + self.discarded;
+        if seen > 0 {
+            self.discarded as f64 / seen as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the wrapped iterator.
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+}
+
+impl<I, K> Iterator for RangeFilter<I, K>
+where
+    I: Iterator,
+    I::Item: Borrow<K>,
+    K: Ord,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        for key in self.iter.by_ref() {
+            if (self.start.as_ref(), self.end.as_ref()).contains(key.borrow()) {
+                self.kept += 1;
+                return Some(key);
+            }
+            self.discarded += 1;
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bits::BitString;
+    use int_key::{self, IntKey};
+    use planner::plan_cover;
+
+    #[test]
+    fn trims_planned_scans_to_the_range() {
+        let keys: Vec<BitString> = (0..=255u8).map(|k| k.to_key()).collect();
+        let cover = int_key::range_cover(3u8, 12u8).unwrap();
+        let plan = plan_cover(&cover, 1);
+
+        let scanned = plan.prefixes.iter().flat_map(|p| {
+            let p = p.as_bit_str();
+            keys.iter().filter(move |k| k.as_bit_str().starts_with(p))
+        });
+        let mut filter = RangeFilter::new(scanned, 3u8.to_key()..=12u8.to_key());
+
+        let kept: Vec<u8> = filter.by_ref().map(|k| u8::from_key(k.as_bit_str()).unwrap()).collect();
+
+        assert_eq!(kept, (3..=12).collect::<Vec<_>>());
+        assert_eq!(filter.kept(), 10);
+        assert_eq!(filter.discarded(), 6);
+        assert_eq!(filter.overfetch(), plan.overfetch());
+    }
+    #[test]
+    fn filters_borrowed_and_owned_keys() {
+        let scanned: Vec<&[u8]> = vec![b"a", b"ab", b"abc", b"b"];
+
+        let borrowed: Vec<&[u8]> = RangeFilter::new(scanned.iter().cloned(), &b"ab"[..]..&b"b"[..]).collect();
+        let owned = RangeFilter::new(scanned.iter().map(|k| k.to_vec()), ..=b"ab".to_vec());
+
+        assert_eq!(borrowed, vec![&b"ab"[..], b"abc"]);
+        assert_eq!(owned.collect::<Vec<_>>(), vec![b"a".to_vec(), b"ab".to_vec()]);
+    }
+    #[test]
+    fn counts_every_discarded_key() {
+        let bits = |s: &str| s.chars().map(|c| c == '1').collect::<BitString>();
+        let scanned = vec![bits("0001"), bits("0100"), bits("0110"), bits("1000")];
+        let mut filter = RangeFilter::new(scanned.into_iter(), (Bound::Excluded(bits("0100")), Bound::Unbounded));
+
+        assert_eq!(filter.next(), Some(bits("0110")));
+        assert_eq!((filter.kept(), filter.discarded()), (1, 2));
+        assert_eq!(filter.next(), Some(bits("1000")));
+        assert_eq!(filter.next(), None);
+        assert_eq!((filter.kept(), filter.discarded()), (2, 2));
+        assert!(filter.contains(&bits("0111")));
+        assert!(!filter.contains(&bits("0")));
+    }
+}
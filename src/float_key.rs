@@ -0,0 +1,137 @@
+//! Order-preserving encodings of IEEE-754 floats.
+//!
+//! Positive floats have their sign bit flipped and negative floats have all of their bits
+//! inverted, so the bit order of two keys matches the numeric order of the floats.
+//!
+//! The order is the IEEE-754 total order, the same one used by `f64::total_cmp`:
+//!
+//! * `-0.0` sorts immediately below `+0.0`, so the two zeros are distinct keys.
+//! * NaNs with the sign bit set sort below negative infinity,
+//!   and NaNs without it sort above positive infinity.
+
+use bits::{self, BitStr, BitString};
+use error::RangePrefixError;
+use int_key::IntKey;
+
+/// Floats with an order-preserving, fixed-width bit encoding.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::FloatKey;
+///
+/// (-1.0f32).to_key() < 0.5f32.to_key();
+/// // true
+/// ```
+pub trait FloatKey: Copy {
+    /// The width of every encoded key, in bits.
+    const BITS: usize;
+
+    /// Encodes the float as a key.
+    fn to_key(self) -> BitString;
+
+    /// Decodes a key produced by [`to_key`](#tymethod.to_key).
+    ///
+    /// Returns `None` if the key is not exactly [`BITS`](#associatedconstant.BITS) long.
+    fn from_key(key: BitStr) -> Option<Self>;
+}
+
+macro_rules! float_key {
+    ($($ty:ty => $bits:ty),*) => {$(
+        impl FloatKey for $ty {
+            const BITS: usize = <$bits as IntKey>::BITS;
+
+            fn to_key(self) -> BitString {
+                let bits = self.to_bits();
+                let sign = 1 << (<Self as FloatKey>::BITS - 1);
+                let ordered = if bits & sign == 0 { bits ^ sign } else { !bits };
+                ordered.to_key()
+            }
+
+            fn from_key(key: BitStr) -> Option<$ty> {
+                let ordered = <$bits>::from_key(key)?;
+                let sign = 1 << (<Self as FloatKey>::BITS - 1);
+                let bits = if ordered & sign == 0 { !ordered } else { ordered ^ sign };
+                Some(<$ty>::from_bits(bits))
+            }
+        }
+    )*};
+}
+
+float_key!(f32 => u32, f64 => u64);
+
+/// Finds the minimal set of prefixes covering the floats in `[lo, hi]`.
+///
+/// Keys order `-0.0` just below `+0.0`, so a range starting at `0.0` excludes `-0.0`.
+/// Start at `-0.0` to include both zeros.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::float_key::range_cover;
+///
+/// range_cover(-3.5f64, 12.0f64).unwrap().len();
+/// // 8
+/// ```
+pub fn range_cover<F: FloatKey>(lo: F, hi: F) -> Result<Vec<BitString>, RangePrefixError> {
+    bits::range_cover(lo.to_key().as_bit_str(), hi.to_key().as_bit_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_follow_total_order() {
+        let values = [
+            -f64::NAN,
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -3.5,
+            -f64::MIN_POSITIVE,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            12.0,
+            f64::MAX,
+            f64::INFINITY,
+            f64::NAN,
+        ];
+        let keys: Vec<BitString> = values.iter().map(|v| v.to_key()).collect();
+
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    }
+    #[test]
+    fn round_trips() {
+        for &v in &[-2.5f32, -0.0, 0.0, 1e-40, f32::INFINITY] {
+            let decoded = f32::from_key(v.to_key().as_bit_str()).unwrap();
+            assert_eq!(decoded.to_bits(), v.to_bits());
+        }
+        assert!(f64::from_key(f64::NAN.to_key().as_bit_str()).unwrap().is_nan());
+        assert_eq!(f64::from_key(1.0f32.to_key().as_bit_str()), None);
+    }
+    #[test]
+    fn cover_contains_exactly_the_range() {
+        let cover = range_cover(-3.5f32, 12.0f32).unwrap();
+        let inside = |v: f32| {
+            let key = v.to_key();
+            cover.iter().any(|p| key.as_bit_str().starts_with(p.as_bit_str()))
+        };
+
+        for &v in &[-3.5, -3.4999998, -0.0, 0.0, 1.0, 11.999999, 12.0] {
+            assert!(inside(v), "{} should be covered", v);
+        }
+        for &v in &[-3.5000002, 12.000001, f32::NEG_INFINITY, f32::INFINITY, f32::NAN] {
+            assert!(!inside(v), "{} should not be covered", v);
+        }
+
+        let covers = |lo: f32, v: f32| range_cover(lo, 1.0).unwrap().iter().any(|p| v.to_key().as_bit_str().starts_with(p.as_bit_str()));
+        assert!(!covers(0.0, -0.0));
+        assert!(covers(-0.0, -0.0) && covers(-0.0, 0.0));
+    }
+    #[test]
+    fn rejects_inverted_range() {
+        assert_eq!(range_cover(1.0f64, -1.0f64), Err(RangePrefixError::InvertedRange));
+        assert_eq!(range_cover(0.0f64, -0.0f64), Err(RangePrefixError::InvertedRange));
+    }
+}
@@ -0,0 +1,406 @@
+//! Redis `SCAN MATCH` patterns for prefix covers.
+//!
+//! Each prefix of a cover could be scanned with its own `prefix*` pattern, but sibling
+//! prefixes that differ only in their last byte can share one pattern with a character
+//! class, so `user:2017-03` to `user:2017-09` become `user:2017-0[3-9]*`.
+//!
+//! Key material is escaped, so keys containing `*`, `?`, `[`, `]` or `\` are matched
+//! literally. Patterns follow the rules of Redis' `stringmatchlen`, which compares the
+//! ends of a class range as signed `char`s; generated ranges never span bytes `0x7f` and
+//! `0x80`, so they mean the same thing either way.
+
+use std::collections::BTreeMap;
+
+use lex::LexPrefix;
+
+/// Finds `SCAN MATCH` patterns that match exactly the keys of a lexicographic cover.
+///
+/// Entries of the same kind that differ only in their last byte share one pattern, with
+/// those bytes in a character class; other entries get a pattern each. Patterns are
+/// returned in the order of the entries they replace.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::glob::scan_patterns;
+/// use binary_prefix::lex::range_cover;
+///
+/// let cover = range_cover(b"user:2017-03", b"user:2017-09").unwrap();
+///
+/// scan_patterns(&cover);
+/// // ["user:2017-0[3-8]*", "user:2017-09"]
+/// ```
+pub fn scan_patterns(cover: &[LexPrefix]) -> Vec<Vec<u8>> {
+    // entries that differ only in their last byte share a pattern
+    let mut groups: Vec<(&[u8], bool, Vec<u8>)> = Vec::new();
+    let mut index = BTreeMap::new();
+    for entry in cover {
+        let is_prefix = matches!(*entry, LexPrefix::Prefix(_));
+        match entry.bytes().split_last() {
+            Some((&last, head)) => {
+                let i = *index.entry((head, is_prefix)).or_insert_with(|| {
+                    groups.push((head, is_prefix, Vec::new()));
+                    groups.len() - 1
+                });
+                groups[i].2.push(last);
+            }
+            None => groups.push((&[], is_prefix, Vec::new())),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(head, is_prefix, mut last)| {
+            let mut pattern = escape(head);
+            if !last.is_empty() {
+                last.sort_unstable();
+                last.dedup();
+                pattern.extend(class(&last));
+            }
+            if is_prefix {
+                pattern.push(b'*');
+            }
+            pattern
+        })
+        .collect()
+}
+
+/// Finds `SCAN MATCH` patterns that match exactly the keys starting with any of `prefixes`.
+///
+/// Prefixes are grouped as by [`scan_patterns`]. This takes the string covers of [`radix`](../radix/index.html) and the byte prefixes
+/// of [`byte_key`](../byte_key/index.html).
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::glob::prefix_patterns;
+/// use binary_prefix::radix::Alphabet;
+///
+/// let cover = Alphabet::decimal().range_cover("0385", "1204").unwrap();
+///
+/// prefix_patterns(&cover);
+/// // ["038[5-9]*", "039*", "0[4-9]*", "1[01]*", "120[0-4]*"]
+/// ```
+pub fn prefix_patterns<P: AsRef<[u8]>>(prefixes: &[P]) -> Vec<Vec<u8>> {
+    let cover: Vec<LexPrefix> = prefixes.iter().map(|p| LexPrefix::Prefix(p.as_ref().to_vec())).collect();
+    scan_patterns(&cover)
+}
+
+/// Escapes the bytes that are special in a pattern, so `key` is matched literally.
+pub fn escape(key: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(key.len());
+    for &byte in key {
+        if let b'*' | b'?' | b'[' | b']' | b'\\' = byte {
+            out.push(b'\\');
+        }
+        out.push(byte);
+    }
+    out
+}
+
+/// Returns `true` if `pattern` matches `key` the way Redis does.
+pub fn matches(pattern: &[u8], key: &[u8]) -> bool {
+    match parse(pattern) {
+        Some(tokens) => match_tokens(&tokens, key),
+        None => false,
+    }
+}
+
+/// Returns `true` if `pattern` matches exactly the keys matched by `entries`, no more and no less.
+///
+/// Only patterns of the shape produced by [`scan_patterns`] can be verified: single-byte
+/// tokens, optionally followed by one trailing `*`. Other patterns are reported as not
+/// matching.
+pub fn verify(pattern: &[u8], entries: &[LexPrefix]) -> bool {
+    let mut tokens = match parse(pattern) {
+        Some(tokens) => tokens,
+        None => return false,
+    };
+    let is_prefix = tokens.last() == Some(&Token::AnyRun);
+    if is_prefix {
+        tokens.pop();
+    }
+    let sets: Option<Vec<[bool; 256]>> = tokens.iter().map(Token::byte_set).collect();
+    let sets = match sets {
+        Some(sets) => sets,
+        None => return false,
+    };
+
+    // the pattern stands for one entry per combination of its bytes
+    let size = sets
+        .iter()
+        .try_fold(1usize, |size, set| size.checked_mul(set.iter().filter(|&&b| b).count()));
+    if size != Some(entries.len()) {
+        return false;
+    }
+
+    let mut seen: Vec<&[u8]> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let bytes = entry.bytes();
+        if matches!(*entry, LexPrefix::Prefix(_)) != is_prefix
+            || bytes.len() != sets.len()
+            || !bytes.iter().zip(&sets).all(|(&b, set)| set[b as usize])
+        {
+            return false;
+        }
+        seen.push(bytes);
+    }
+    seen.sort_unstable();
+    seen.windows(2).all(|w| w[0] != w[1])
+}
+
+/// One element of a parsed pattern.
+#[derive(Clone, PartialEq)]
+enum Token {
+    Byte(u8),
+    /// `?`
+    AnyByte,
+    /// `*`
+    AnyRun,
+    Class(Box<[bool; 256]>),
+}
+
+impl Token {
+    /// Returns the bytes matched by a single-byte token.
+    fn byte_set(&self) -> Option<[bool; 256]> {
+        match *self {
+            Token::Byte(b) => {
+                let mut set = [false; 256];
+                set[b as usize] = true;
+                Some(set)
+            }
+            Token::AnyByte => Some([true; 256]),
+            Token::AnyRun => None,
+            Token::Class(ref set) => Some(**set),
+        }
+    }
+}
+
+/// Parses a pattern, or returns `None` if it ends inside an escape or a class.
+fn parse(pattern: &[u8]) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < pattern.len() {
+        let token = match pattern[i] {
+            b'*' => Token::AnyRun,
+            b'?' => Token::AnyByte,
+            b'\\' => {
+                i += 1;
+                Token::Byte(*pattern.get(i)?)
+            }
+            b'[' => {
+                i += 1;
+                let negate = pattern.get(i) == Some(&b'^');
+                if negate {
+                    i += 1;
+                }
+                let mut set = [false; 256];
+                loop {
+                    match *pattern.get(i)? {
+                        b']' => break,
+                        b'\\' => {
+                            i += 1;
+                            set[*pattern.get(i)? as usize] = true;
+                        }
+                        start if pattern.get(i + 1) == Some(&b'-') && i + 2 < pattern.len() => {
+                            let end = pattern[i + 2];
+                            // Redis compares range ends as signed chars
+                            let (lo, hi) = ((start as i8).min(end as i8), (start as i8).max(end as i8));
+                            for b in lo..=hi {
+                                set[b as u8 as usize] = true;
+                            }
+                            i += 2;
+                        }
+                        b => set[b as usize] = true,
+                    }
+                    i += 1;
+                }
+                if negate {
+                    for b in set.iter_mut() {
+                        *b = !*b;
+                    }
+                }
+                Token::Class(Box::new(set))
+            }
+            b => Token::Byte(b),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    Some(tokens)
+}
+
+fn match_tokens(tokens: &[Token], key: &[u8]) -> bool {
+    // `reachable[j]` is true if the tokens so far can match `key[..j]`
+    let mut reachable = vec![false; key.len() + 1];
+    reachable[0] = true;
+    for token in tokens {
+        let mut next = vec![false; key.len() + 1];
+        match *token {
+            Token::AnyRun => {
+                let mut any = false;
+                for j in 0..=key.len() {
+                    any |= reachable[j];
+                    next[j] = any;
+                }
+            }
+            _ => {
+                let set = token.byte_set().unwrap();
+                for j in 0..key.len() {
+                    next[j + 1] = reachable[j] && set[key[j] as usize];
+                }
+            }
+        }
+        reachable = next;
+    }
+    reachable[key.len()]
+}
+
+/// Renders the shortest pattern element matching exactly the bytes in `sorted`.
+fn class(sorted: &[u8]) -> Vec<u8> {
+    match sorted.len() {
+        1 => escape(sorted),
+        256 => b"?".to_vec(),
+        _ => {
+            let complement: Vec<u8> = (0..=255u8).filter(|b| sorted.binary_search(b).is_err()).collect();
+            let mut plain = b"[".to_vec();
+            plain.extend(class_body(sorted));
+            plain.push(b']');
+            let mut negated = b"[^".to_vec();
+            negated.extend(class_body(&complement));
+            negated.push(b']');
+            if negated.len() < plain.len() {
+                negated
+            } else {
+                plain
+            }
+        }
+    }
+}
+
+/// Renders the inside of a class, using ranges for runs of three or more bytes.
+fn class_body(sorted: &[u8]) -> Vec<u8> {
+    // a byte that ends a range can't be escaped, so these are only written on their own
+    let special = |b: u8| matches!(b, b'\\' | b']' | b'-' | b'^');
+    let single = |out: &mut Vec<u8>, b: u8| {
+        if special(b) {
+            out.push(b'\\');
+        }
+        out.push(b);
+    };
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < sorted.len() {
+        // runs stop at 0x80, where signed and unsigned order disagree
+        let mut j = i;
+        while j + 1 < sorted.len() && sorted[j + 1] as u16 == sorted[j] as u16 + 1 && sorted[j + 1] != 0x80 {
+            j += 1;
+        }
+        let (mut lo, mut hi) = (sorted[i], sorted[j]);
+        while lo < hi && special(lo) {
+            single(&mut out, lo);
+            lo += 1;
+        }
+        let mut tail = Vec::new();
+        while lo < hi && special(hi) {
+            tail.push(hi);
+            hi -= 1;
+        }
+        if hi - lo >= 2 {
+            out.extend_from_slice(&[lo, b'-', hi]);
+        } else {
+            for b in lo..=hi {
+                single(&mut out, b);
+            }
+        }
+        for &b in tail.iter().rev() {
+            single(&mut out, b);
+        }
+        i = j + 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lex::range_cover;
+    use radix::Alphabet;
+
+    fn strings(patterns: Vec<Vec<u8>>) -> Vec<String> {
+        patterns.into_iter().map(|p| String::from_utf8(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn collapses_siblings_into_classes() {
+        let cover = range_cover(b"user:2017-03", b"user:2017-09").unwrap();
+        let patterns = scan_patterns(&cover);
+
+        assert_eq!(strings(patterns.clone()), vec!["user:2017-0[3-8]*", "user:2017-09"]);
+        assert!(verify(&patterns[0], &cover[..6]));
+        assert!(verify(&patterns[1], &cover[6..]));
+
+        let cover = Alphabet::decimal().range_cover("0385", "1204").unwrap();
+        assert_eq!(strings(prefix_patterns(&cover)), vec!["038[5-9]*", "039*", "0[4-9]*", "1[01]*", "120[0-4]*"]);
+    }
+    #[test]
+    fn escapes_key_material() {
+        assert_eq!(escape(b"a*b?c[d]e\\f^-"), b"a\\*b\\?c\\[d\\]e\\\\f^-".to_vec());
+
+        let prefixes: Vec<&[u8]> = vec![b"k*[", b"k*\\", b"k*]", b"k*^"];
+        let patterns = prefix_patterns(&prefixes);
+
+        assert_eq!(patterns, vec![b"k\\*[[\\\\\\]\\^]*".to_vec()]);
+        assert!(matches(&patterns[0], b"k*]x"));
+        assert!(!matches(&patterns[0], b"kx]x"));
+        assert!(!matches(&patterns[0], b"k*a"));
+    }
+    #[test]
+    fn uses_wildcards_and_negation_when_shorter() {
+        let all: Vec<Vec<u8>> = (0..=255u8).map(|b| vec![b'x', b]).collect();
+        let most: Vec<Vec<u8>> = (0..=255u8).filter(|&b| b != b'q').map(|b| vec![b'x', b]).collect();
+
+        assert_eq!(prefix_patterns(&all), vec![b"x?*".to_vec()]);
+        assert_eq!(prefix_patterns(&most), vec![b"x[^q]*".to_vec()]);
+        assert_eq!(scan_patterns(&[LexPrefix::Prefix(vec![])]), vec![b"*".to_vec()]);
+        assert_eq!(scan_patterns(&[LexPrefix::Key(vec![])]), vec![Vec::<u8>::new()]);
+    }
+    #[test]
+    fn patterns_match_exactly_their_cover() {
+        let symbols = [0u8, b'-', b'\\', b']', b'^', b'a', 0x7f, 0x80, 0xff];
+        let mut keys = vec![vec![]];
+        for &a in &symbols {
+            keys.push(vec![a]);
+            for &b in &symbols {
+                keys.push(vec![a, b]);
+            }
+        }
+        keys.sort();
+
+        for lo in &keys {
+            for hi in keys.iter().filter(|hi| lo <= *hi) {
+                let cover = range_cover(lo, hi).unwrap();
+                let patterns = scan_patterns(&cover);
+                assert!(patterns.len() <= cover.len());
+                for key in &keys {
+                    let hits = patterns.iter().filter(|p| matches(p, key)).count();
+                    let expected = if lo <= key && key <= hi { 1 } else { 0 };
+                    assert_eq!(hits, expected, "{:?} in {:?}..={:?}", key, lo, hi);
+                }
+            }
+        }
+    }
+    #[test]
+    fn verifies_patterns_against_entries() {
+        let prefixes = |list: &[&[u8]]| list.iter().map(|p| LexPrefix::Prefix(p.to_vec())).collect::<Vec<_>>();
+
+        assert!(verify(b"a[b-d]*", &prefixes(&[b"ab", b"ac", b"ad"])));
+        assert!(!verify(b"a[b-d]*", &prefixes(&[b"ab", b"ac"])));
+        assert!(!verify(b"a[b-d]*", &prefixes(&[b"ab", b"ac", b"ac"])));
+        assert!(!verify(b"a[b-d]", &prefixes(&[b"ab", b"ac", b"ad"])));
+        assert!(!verify(b"a*b", &prefixes(&[b"ab"])));
+        assert!(!verify(b"a[b", &prefixes(&[b"ab"])));
+        assert!(verify(b"\\*", &[LexPrefix::Key(b"*".to_vec())]));
+    }
+}
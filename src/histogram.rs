@@ -0,0 +1,247 @@
+//! Estimates of where keys actually live.
+//!
+//! The planner's default assumption is that keys are spread evenly over the key space,
+//! so merging two prefixes costs the space between them. Real keys cluster: merging
+//! across a gap with no keys in it is free, while merging into a crowded neighbour is
+//! not. A [`KeyHistogram`] built from a sample of keys lets the planner tell the two
+//! apart.
+//!
+//! Histograms can be built offline and shipped to query nodes with
+//! [`to_bytes`](struct.KeyHistogram.html#method.to_bytes) and
+//! [`from_bytes`](struct.KeyHistogram.html#method.from_bytes).
+
+use std::collections::BTreeMap;
+
+use bits::{BitStr, BitString};
+use codec::{Reader, Writer};
+use error::DecodeError;
+use planner::KeyDistribution;
+
+const MAGIC: &[u8] = b"BPKH";
+const VERSION: u8 = 1;
+
+/// Counts of sampled keys per prefix, up to a fixed depth.
+///
+/// Counts are exact for prefixes no longer than the depth. Below the depth, keys are
+/// assumed to be spread evenly.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::histogram::KeyHistogram;
+/// use binary_prefix::int_key::{range_cover, IntKey};
+/// use binary_prefix::planner::plan_cover_with;
+///
+/// // none of the sampled keys under 0000 are outside the range
+/// let mut histogram = KeyHistogram::new(8);
+/// histogram.extend([3u8, 4, 12, 200, 201].iter().map(|k| k.to_key()));
+///
+/// let cover = range_cover(3u8, 12u8).unwrap();
+/// let plan = plan_cover_with(&cover, 1, &histogram);
+///
+/// &plan.prefixes;
+/// // [0000]
+/// plan.wasted;
+/// // 0.0
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyHistogram {
+    depth: usize,
+    total: u64,
+    /// Sampled keys cut to at most `depth` bits, with how often each occurs.
+    leaves: BTreeMap<BitString, u64>,
+}
+
+impl KeyHistogram {
+    /// Creates an empty histogram that counts prefixes of up to `depth` bits.
+    pub fn new(depth: usize) -> KeyHistogram {
+        KeyHistogram {
+            depth,
+            total: 0,
+            leaves: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the length of the longest prefix counted exactly.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the number of sampled keys.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns `true` if no keys have been sampled.
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Adds a sampled key.
+    pub fn insert(&mut self, key: BitStr) {
+        let leaf = key.slice(..self.depth.min(key.len())).to_bit_string();
+        *self.leaves.entry(leaf).or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    /// Returns the number of sampled keys that start with `prefix`.
+    ///
+    /// Prefixes longer than the depth are counted as their ancestor at the depth.
+    pub fn count(&self, prefix: BitStr) -> u64 {
+        let prefix = prefix.slice(..self.depth.min(prefix.len()));
+        // keys under a prefix sort directly after it
+        self.leaves
+            .range(prefix.to_bit_string()..)
+            .take_while(|&(leaf, _)| leaf.as_bit_str().starts_with(prefix))
+            .map(|(_, &count)| count)
+            .sum()
+    }
+
+    /// Estimates the fraction of all keys that start with `prefix`.
+    ///
+    /// An empty histogram knows nothing about the keys and estimates them as evenly spread.
+    pub fn estimate(&self, prefix: BitStr) -> f64 {
+        if self.total == 0 {
+            return 0.5f64.powi(prefix.len() as i32);
+        }
+        let below = prefix.len().saturating_sub(self.depth);
+        self.count(prefix) as f64 / self.total as f64 * 0.5f64.powi(below as i32)
+    }
+
+    /// Serializes the histogram into a compact, versioned binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(MAGIC, VERSION);
+        writer.u64(self.depth as u64);
+        writer.u64(self.leaves.len() as u64);
+        for (leaf, &count) in &self.leaves {
+            writer.bits(leaf.as_bit_str());
+            writer.u64(count);
+        }
+        writer.finish()
+    }
+
+    /// Reads a histogram written by [`to_bytes`](#method.to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<KeyHistogram, DecodeError> {
+        let (mut reader, version) = Reader::new(bytes, MAGIC)?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let mut histogram = KeyHistogram::new(reader.usize()?);
+        for _ in 0..reader.u64()? {
+            let leaf = reader.bits()?;
+            let count = reader.u64()?;
+            if leaf.len() > histogram.depth || count == 0 {
+                return Err(DecodeError::Corrupt);
+            }
+            histogram.total = histogram.total.checked_add(count).ok_or(DecodeError::Corrupt)?;
+            if histogram.leaves.insert(leaf, count).is_some() {
+                return Err(DecodeError::Corrupt);
+            }
+        }
+        reader.finish()?;
+        Ok(histogram)
+    }
+}
+
+impl KeyDistribution for KeyHistogram {
+    fn weight(&self, prefix: BitStr) -> f64 {
+        self.estimate(prefix)
+    }
+}
+
+impl Extend<BitString> for KeyHistogram {
+    fn extend<I: IntoIterator<Item = BitString>>(&mut self, keys: I) {
+        for key in keys {
+            self.insert(key.as_bit_str());
+        }
+    }
+}
+
+impl<'a> Extend<BitStr<'a>> for KeyHistogram {
+    fn extend<I: IntoIterator<Item = BitStr<'a>>>(&mut self, keys: I) {
+        for key in keys {
+            self.insert(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use int_key::{self, IntKey};
+    use planner::{plan_cover, plan_cover_with};
+
+    fn bits(s: &str) -> BitString {
+        s.chars().map(|c| c == '1').collect()
+    }
+
+    fn sample<K: IntKey>(depth: usize, keys: &[K]) -> KeyHistogram {
+        let mut histogram = KeyHistogram::new(depth);
+        histogram.extend(keys.iter().map(|k| k.to_key()));
+        histogram
+    }
+
+    #[test]
+    fn counts_prefixes_up_to_depth() {
+        let histogram = sample(4, &[0x0000u16, 0x0fff, 0x1000, 0x8000, 0x8001]);
+
+        assert_eq!(histogram.total(), 5);
+        assert_eq!(histogram.count(BitString::new().as_bit_str()), 5);
+        assert_eq!(histogram.count(bits("0").as_bit_str()), 3);
+        assert_eq!(histogram.count(bits("0000").as_bit_str()), 2);
+        assert_eq!(histogram.count(bits("0001").as_bit_str()), 1);
+        assert_eq!(histogram.count(bits("11").as_bit_str()), 0);
+        // deeper prefixes fall back to their ancestor at the depth
+        assert_eq!(histogram.count(bits("10001111").as_bit_str()), 2);
+        assert_eq!(histogram.estimate(bits("10001").as_bit_str()), 0.2);
+    }
+    #[test]
+    fn empty_histogram_is_uniform() {
+        let histogram = KeyHistogram::new(8);
+        let cover = int_key::range_cover(1234u16, 50000u16).unwrap();
+
+        assert!(histogram.is_empty());
+        assert_eq!(histogram.estimate(bits("010").as_bit_str()), 0.125);
+        assert_eq!(plan_cover_with(&cover, 4, &histogram), plan_cover(&cover, 4));
+    }
+    #[test]
+    fn merges_across_empty_gaps() {
+        // keys were sampled from 0 to 12 but none from 13 to 15
+        let keys: Vec<u8> = (0..=12).chain(100..=200).collect();
+        let histogram = sample(8, &keys);
+        let cover = int_key::range_cover(3u8, 12u8).unwrap();
+
+        let uniform = plan_cover(&cover, 3);
+        let sampled = plan_cover_with(&cover, 3, &histogram);
+
+        assert_eq!(uniform.prefixes, vec![bits("00000"), bits("000010"), bits("00001100")]);
+        assert_eq!(sampled.prefixes, vec![bits("00000011"), bits("000001"), bits("00001")]);
+        assert_eq!(sampled.wasted, 0.0);
+        assert!((sampled.fetched - 10.0 / keys.len() as f64).abs() < 1e-12);
+    }
+    #[test]
+    fn round_trips_through_bytes() {
+        let histogram = sample(12, &[1u16, 2, 3, 3, 500, 40000, 65535]);
+
+        let bytes = histogram.to_bytes();
+
+        assert_eq!(KeyHistogram::from_bytes(&bytes), Ok(histogram));
+        assert_eq!(KeyHistogram::from_bytes(&KeyHistogram::new(3).to_bytes()), Ok(KeyHistogram::new(3)));
+    }
+    #[test]
+    fn rejects_malformed_bytes() {
+        let bytes = sample(12, &[1u16, 2, 3]).to_bytes();
+
+        assert_eq!(KeyHistogram::from_bytes(b"nope"), Err(DecodeError::UnknownFormat));
+        assert_eq!(KeyHistogram::from_bytes(&bytes[..bytes.len() - 1]), Err(DecodeError::UnexpectedEnd));
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()] = VERSION + 1;
+        assert_eq!(KeyHistogram::from_bytes(&newer), Err(DecodeError::UnsupportedVersion(VERSION + 1)));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(KeyHistogram::from_bytes(&trailing), Err(DecodeError::Corrupt));
+    }
+}
@@ -0,0 +1,135 @@
+//! Order-preserving encodings of primitive integers.
+//!
+//! Integers are encoded big-endian at their full width, so the bit order of two keys
+//! matches the numeric order of the integers. Signed integers have their sign bit
+//! flipped, which moves negative numbers below zero.
+
+use std::mem;
+
+use bits::{self, BitStr, BitString};
+use error::RangePrefixError;
+
+/// Integers with an order-preserving, fixed-width bit encoding.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::IntKey;
+///
+/// (-1i8).to_key().to_string();
+/// // "01111111"
+/// 1i8.to_key().to_string();
+/// // "10000001"
+/// ```
+pub trait IntKey: Copy + Ord {
+    /// The width of every encoded key, in bits.
+    const BITS: usize;
+
+    /// Encodes the integer as a key.
+    fn to_key(self) -> BitString;
+
+    /// Decodes a key produced by [`to_key`](#tymethod.to_key).
+    ///
+    /// Returns `None` if the key is not exactly [`BITS`](#associatedconstant.BITS) long.
+    fn from_key(key: BitStr) -> Option<Self>;
+}
+
+macro_rules! unsigned_key {
+    ($($ty:ty),*) => {$(
+        impl IntKey for $ty {
+            const BITS: usize = <$ty>::BITS as usize;
+
+            fn to_key(self) -> BitString {
+                BitString::from_bytes(&self.to_be_bytes())
+            }
+
+            fn from_key(key: BitStr) -> Option<$ty> {
+                if key.len() != <$ty as IntKey>::BITS {
+                    return None;
+                }
+                let mut bytes = [0; mem::size_of::<$ty>()];
+                bytes.copy_from_slice(&key.to_bytes());
+                Some(<$ty>::from_be_bytes(bytes))
+            }
+        }
+    )*};
+}
+
+macro_rules! signed_key {
+    ($($ty:ty => $unsigned:ty),*) => {$(
+        impl IntKey for $ty {
+            const BITS: usize = <$ty>::BITS as usize;
+
+            fn to_key(self) -> BitString {
+                ((self as $unsigned) ^ (1 << (<$ty as IntKey>::BITS - 1))).to_key()
+            }
+
+            fn from_key(key: BitStr) -> Option<$ty> {
+                <$unsigned>::from_key(key).map(|bits| (bits ^ (1 << (<$ty as IntKey>::BITS - 1))) as $ty)
+            }
+        }
+    )*};
+}
+
+unsigned_key!(u8, u16, u32, u64, u128);
+signed_key!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+/// Finds the minimal set of prefixes covering the integers in `[lo, hi]`.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::int_key::range_cover;
+///
+/// range_cover(3u8, 12u8);
+/// // Ok([00000011, 000001, 000010, 00001100])
+/// ```
+pub fn range_cover<K: IntKey>(lo: K, hi: K) -> Result<Vec<BitString>, RangePrefixError> {
+    bits::range_cover(lo.to_key().as_bit_str(), hi.to_key().as_bit_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_value<K: IntKey>(prefix: &BitString) -> (K, K) {
+        let low = prefix.clone() + &BitString::zeros(K::BITS - prefix.len());
+        let high = prefix.clone() + &BitString::from(vec![true; K::BITS - prefix.len()]);
+        (K::from_key(low.as_bit_str()).unwrap(), K::from_key(high.as_bit_str()).unwrap())
+    }
+
+    #[test]
+    fn signed_keys_sort_numerically() {
+        let values = [i32::MIN, -70000, -1, 0, 1, 255, 70000, i32::MAX];
+        let keys: Vec<BitString> = values.iter().map(|v| v.to_key()).collect();
+
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert!(keys.iter().all(|k| k.len() == 32));
+    }
+    #[test]
+    fn round_trips() {
+        for &v in &[i64::MIN, -5, 0, 5, i64::MAX] {
+            assert_eq!(i64::from_key(v.to_key().as_bit_str()), Some(v));
+        }
+        for &v in &[0u128, 1, u128::MAX / 3, u128::MAX] {
+            assert_eq!(u128::from_key(v.to_key().as_bit_str()), Some(v));
+        }
+        assert_eq!(u16::from_key(7u8.to_key().as_bit_str()), None);
+    }
+    #[test]
+    fn covers_signed_range() {
+        for &(lo, hi) in &[(-128i8, 127i8), (-3, 2), (-1, 0), (5, 5), (-100, -37)] {
+            let cover = range_cover(lo, hi).unwrap();
+            let spans: Vec<(i8, i8)> = cover.iter().map(key_value).collect();
+
+            assert_eq!(spans.first().unwrap().0, lo);
+            assert_eq!(spans.last().unwrap().1, hi);
+            assert!(spans.windows(2).all(|w| w[0].1 + 1 == w[1].0));
+        }
+    }
+    #[test]
+    fn rejects_inverted_range() {
+        assert_eq!(range_cover(5u64, 4u64), Err(RangePrefixError::InvertedRange));
+        assert_eq!(range_cover(0i16, -1i16), Err(RangePrefixError::InvertedRange));
+    }
+}
@@ -0,0 +1,190 @@
+//! Covers as half-open key ranges.
+//!
+//! Ordered stores such as etcd, TiKV and DynamoDB scan a range `[start, end)` directly
+//! instead of a prefix. Every prefix is such a range, ending at its
+//! [successor](../bits/fn.prefix_successor.html), and neighbouring prefixes of a cover
+//! join into one range.
+
+use bits::{self, BitString};
+use lex::{self, LexPrefix};
+
+/// The keys from `start`, inclusive, up to `end`, exclusive.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyRange<K> {
+    /// The smallest key in the range.
+    pub start: K,
+    /// The smallest key above the range, or `None` if the range has no upper bound.
+    pub end: Option<K>,
+}
+
+impl<K: Ord> KeyRange<K> {
+    /// Returns `true` if `key` is within the range.
+    pub fn contains(&self, key: &K) -> bool {
+        *key >= self.start && self.end.as_ref().map_or(true, |end| key < end)
+    }
+}
+
+impl KeyRange<BitString> {
+    /// Converts the range over bit strings into a range over bytes.
+    ///
+    /// Both ends are padded with zeros to a whole byte. The byte range holds the same
+    /// keys as long as every key is at least as long as the padded ends, as fixed-width
+    /// keys are.
+    pub fn to_bytes(&self) -> KeyRange<Vec<u8>> {
+        KeyRange {
+            start: self.start.to_bytes(),
+            end: self.end.as_ref().map(BitString::to_bytes),
+        }
+    }
+}
+
+/// Converts a cover of bit prefixes into the fewest half-open ranges holding the same keys.
+///
+/// `cover` must be sorted and free of overlaps, as returned by the `range_cover` functions.
+/// Keys are taken to have a fixed width, so a range ending at `00001` and one starting at
+/// `000010` meet and are joined.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::int_key::range_cover;
+/// use binary_prefix::key_range::bit_ranges;
+///
+/// let cover = range_cover(3u8, 12u8).unwrap();
+/// let ranges = bit_ranges(&cover);
+///
+/// ranges[0].to_bytes();
+/// // KeyRange { start: [3], end: Some([13]) }
+/// ```
+pub fn bit_ranges(cover: &[BitString]) -> Vec<KeyRange<BitString>> {
+    // ignoring trailing zeros, which pad a bound out to the key width
+    let trimmed = |bits: &BitString| {
+        let bits = bits.as_bit_str();
+        bits.slice(..bits.rposition(true).map_or(0, |i| i + 1)).to_bit_string()
+    };
+    let ranges = cover.iter().map(|prefix| KeyRange {
+        start: prefix.clone(),
+        end: bits::prefix_successor(prefix.as_bit_str()),
+    });
+    join(ranges, |end, start| trimmed(end) == trimmed(start))
+}
+
+/// Converts a lexicographic cover into the fewest half-open ranges holding the same keys.
+///
+/// A [`LexPrefix::Key`] entry becomes the range from the key up to the key followed by
+/// a zero byte.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::key_range::lex_ranges;
+/// use binary_prefix::lex::range_cover;
+///
+/// let cover = range_cover(b"user:10", b"user:20").unwrap();
+///
+/// lex_ranges(&cover);
+/// // [KeyRange { start: "user:10", end: Some("user:20\0") }]
+/// ```
+pub fn lex_ranges(cover: &[LexPrefix]) -> Vec<KeyRange<Vec<u8>>> {
+    let ranges = cover.iter().map(|entry| match *entry {
+        LexPrefix::Key(ref key) => {
+            let mut end = key.clone();
+            end.push(0);
+            KeyRange {
+                start: key.clone(),
+                end: Some(end),
+            }
+        }
+        LexPrefix::Prefix(ref prefix) => KeyRange {
+            start: prefix.clone(),
+            end: lex::prefix_successor(prefix),
+        },
+    });
+    join(ranges, |end, start| end == start)
+}
+
+/// Joins ranges, in ascending order, that end where the next one starts according to `meets`.
+fn join<K, I, F>(ranges: I, meets: F) -> Vec<KeyRange<K>>
+where
+    I: Iterator<Item = KeyRange<K>>,
+    F: Fn(&K, &K) -> bool,
+{
+    let mut out: Vec<KeyRange<K>> = Vec::new();
+    for range in ranges {
+        match out.last_mut() {
+            Some(last) if last.end.as_ref().is_some_and(|end| meets(end, &range.start)) => last.end = range.end,
+            _ => out.push(range),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use int_key::{self, IntKey};
+    use planner::plan_cover;
+
+    fn bits(s: &str) -> BitString {
+        s.chars().map(|c| c == '1').collect()
+    }
+
+    #[test]
+    fn finds_successors() {
+        assert_eq!(bits::prefix_successor(bits("0101").as_bit_str()), Some(bits("011")));
+        assert_eq!(bits::prefix_successor(bits("0").as_bit_str()), Some(bits("1")));
+        assert_eq!(bits::prefix_successor(bits("111").as_bit_str()), None);
+        assert_eq!(bits::prefix_successor(BitString::new().as_bit_str()), None);
+
+        assert_eq!(lex::prefix_successor(b"abc"), Some(b"abd".to_vec()));
+        assert_eq!(lex::prefix_successor(b"a\xff"), Some(b"b".to_vec()));
+        assert_eq!(lex::prefix_successor(b"\xff"), None);
+        assert_eq!(lex::prefix_successor(b""), None);
+    }
+    #[test]
+    fn joins_a_contiguous_cover() {
+        let cover = int_key::range_cover(3u8, 12u8).unwrap();
+
+        let ranges = bit_ranges(&cover);
+
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].to_bytes(), KeyRange {
+            start: vec![3],
+            end: Some(vec![13]),
+        });
+        assert_eq!(bit_ranges(&int_key::range_cover(250u8, 255u8).unwrap())[0].end, None);
+    }
+    #[test]
+    fn bit_ranges_hold_the_cover() {
+        let cover = plan_cover(&int_key::range_cover(1000u16, 40000u16).unwrap(), 3).prefixes;
+        let ranges = bit_ranges(&cover);
+
+        for key in (0..=u16::MAX).step_by(7) {
+            let key_bits = key.to_key();
+            let in_cover = cover.iter().any(|p| key_bits.as_bit_str().starts_with(p.as_bit_str()));
+            assert_eq!(ranges.iter().filter(|r| r.contains(&key_bits)).count(), in_cover as usize);
+            assert_eq!(ranges.iter().filter(|r| r.to_bytes().contains(&key_bits.to_bytes())).count(), in_cover as usize);
+        }
+    }
+    #[test]
+    fn lex_ranges_hold_the_cover() {
+        let symbols = [0u8, 1, b'a', 0xff];
+        let mut keys = vec![vec![]];
+        for &a in &symbols {
+            keys.push(vec![a]);
+            for &b in &symbols {
+                keys.push(vec![a, b]);
+            }
+        }
+
+        for lo in &keys {
+            for hi in keys.iter().filter(|hi| lo <= *hi) {
+                let ranges = lex_ranges(&lex::range_cover(lo, hi).unwrap());
+                assert_eq!(ranges.len(), 1);
+                for key in &keys {
+                    assert_eq!(ranges[0].contains(key), lo <= key && key <= hi);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Prefix covers for variable-length byte strings in lexicographic order.
+//!
+//! Object keys in S3 have different lengths, and `"abc" < "abcd" < "abd"`. The fixed-width
+//! covers elsewhere in this crate rely on [`pad`](../fn.pad.html) to line keys up, which
+//! changes their order. The covers here work on the keys as they are.
+//!
+//! A bound can itself be a prefix of other keys, so a range is not always a union of
+//! prefixes: `["", "ab"]` contains `"a"` and `"ab"` but not `"ac"` or `"aba"`, so neither
+//! `"a"` nor `"ab"` can be issued as a prefix. Covers therefore mix [`LexPrefix::Prefix`]
+//! entries with [`LexPrefix::Key`] entries for such single keys.
+
+use std::ops::Bound;
+
+use error::RangePrefixError;
+
+/// One entry of a lexicographic range cover.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LexPrefix {
+    /// Exactly this key.
+    Key(Vec<u8>),
+    /// Every key that starts with these bytes, including the bytes themselves.
+    Prefix(Vec<u8>),
+}
+
+impl LexPrefix {
+    /// Returns the bytes of the key or prefix.
+    pub fn bytes(&self) -> &[u8] {
+        match *self {
+            LexPrefix::Key(ref bytes) | LexPrefix::Prefix(ref bytes) => bytes,
+        }
+    }
+
+    /// Returns `true` if `key` is matched by this entry.
+    pub fn matches(&self, key: &[u8]) -> bool {
+        match *self {
+            LexPrefix::Key(ref bytes) => key == &bytes[..],
+            LexPrefix::Prefix(ref bytes) => key.starts_with(bytes),
+        }
+    }
+}
+
+/// Finds the minimal cover of the byte strings in `[lo, hi]`, in lexicographic order.
+///
+/// Keys may have any length, including zero. Entries are returned in ascending order and
+/// never overlap.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::lex::{range_cover, LexPrefix};
+///
+/// range_cover(b"ab", b"abc");
+/// // Ok([Key("ab"), Prefix("ab\0"), Prefix("ab\x01"), ..., Prefix("abb"), Key("abc")])
+/// ```
+pub fn range_cover(lo: &[u8], hi: &[u8]) -> Result<Vec<LexPrefix>, RangePrefixError> {
+    bounds_cover(Bound::Included(lo), Bound::Included(hi))
+}
+
+/// Finds the minimal cover of the byte strings between two bounds of any kind.
+///
+/// A range that holds no keys, such as one whose bounds are equal but not both inclusive,
+/// has an empty cover. A start greater than the end is an error.
+pub(crate) fn bounds_cover(start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Vec<LexPrefix>, RangePrefixError> {
+    if let (Bound::Included(lo) | Bound::Excluded(lo), Bound::Included(hi) | Bound::Excluded(hi)) = (start, end) {
+        if lo > hi {
+            return Err(RangePrefixError::InvertedRange);
+        }
+    }
+
+    // the smallest key above `lo` is `lo` followed by a zero byte
+    let lo = match start {
+        Bound::Included(lo) => lo.to_vec(),
+        Bound::Excluded(lo) => prefixed(lo, 0),
+        Bound::Unbounded => Vec::new(),
+    };
+
+    let mut cover = Vec::new();
+    match end {
+        Bound::Included(hi) if lo[..] <= *hi => between(&lo, hi, true, &mut cover),
+        Bound::Excluded(hi) if lo[..] < *hi => between(&lo, hi, false, &mut cover),
+        Bound::Unbounded => at_least(Vec::new(), &lo, &mut cover),
+        _ => {}
+    }
+    Ok(cover)
+}
+
+/// Covers the keys from `lo` up to `hi`, which must not be less than `lo`.
+fn between(lo: &[u8], hi: &[u8], inclusive: bool, cover: &mut Vec<LexPrefix>) {
+    let base_len = lo.iter().zip(hi).take_while(|&(a, b)| a == b).count();
+
+    if base_len == lo.len() {
+        // every key starting with `lo` is at least `lo`
+        at_most(lo.to_vec(), &hi[base_len..], inclusive, cover);
+        return;
+    }
+
+    let base = &lo[..base_len];
+    let (lo_byte, hi_byte) = (lo[base_len], hi[base_len]);
+
+    at_least(prefixed(base, lo_byte), &lo[base_len + 1..], cover);
+    for byte in lo_byte as u16 + 1..hi_byte as u16 {
+        cover.push(LexPrefix::Prefix(prefixed(base, byte as u8)));
+    }
+    at_most(prefixed(base, hi_byte), &hi[base_len + 1..], inclusive, cover);
+}
+
+/// Covers the keys starting with `head` whose remainder is at least `rest`.
+fn at_least(mut head: Vec<u8>, rest: &[u8], cover: &mut Vec<LexPrefix>) {
+    let mut siblings = Vec::new();
+    for &byte in rest {
+        siblings.push((head.clone(), byte));
+        head.push(byte);
+    }
+    cover.push(LexPrefix::Prefix(head));
+    for (parent, byte) in siblings.into_iter().rev() {
+        for next in byte as u16 + 1..=255 {
+            cover.push(LexPrefix::Prefix(prefixed(&parent, next as u8)));
+        }
+    }
+}
+
+/// Covers the keys starting with `head` whose remainder is below `rest`, or equal to it if `inclusive`.
+fn at_most(mut head: Vec<u8>, rest: &[u8], inclusive: bool, cover: &mut Vec<LexPrefix>) {
+    for &byte in rest {
+        // `head` itself sorts before every longer key under it
+        cover.push(LexPrefix::Key(head.clone()));
+        for lower in 0..byte {
+            cover.push(LexPrefix::Prefix(prefixed(&head, lower)));
+        }
+        head.push(byte);
+    }
+    if inclusive {
+        cover.push(LexPrefix::Key(head));
+    }
+}
+
+/// Finds the smallest byte string greater than every byte string starting with `prefix`.
+///
+/// This is the `range_end` etcd uses for prefix queries: the last byte that is not `0xff`
+/// is incremented and everything after it is dropped. A prefix made only of `0xff` bytes,
+/// including the empty prefix, has no successor, so its range is unbounded above.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::lex::prefix_successor;
+///
+/// prefix_successor(b"user:\xff");
+/// // Some("user;")
+/// prefix_successor(b"\xff\xff");
+/// // None
+/// ```
+pub fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|&b| b != 0xff)?;
+    let mut next = prefix[..=last].to_vec();
+    next[last] += 1;
+    Some(next)
+}
+
+fn prefixed(head: &[u8], byte: u8) -> Vec<u8> {
+    let mut out = Vec::with_capacity(head.len() + 1);
+    out.extend_from_slice(head);
+    out.push(byte);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn universe() -> Vec<Vec<u8>> {
+        let symbols = [0u8, 1, b'a', 0xff];
+        let mut keys = vec![vec![]];
+        for &a in &symbols {
+            keys.push(vec![a]);
+            for &b in &symbols {
+                keys.push(vec![a, b]);
+            }
+        }
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn covers_exactly() {
+        let keys = universe();
+        for lo in &keys {
+            for hi in keys.iter().filter(|hi| lo <= *hi) {
+                let cover = range_cover(lo, hi).unwrap();
+                for key in &keys {
+                    let hits = cover.iter().filter(|entry| entry.matches(key)).count();
+                    let expected = if lo <= key && key <= hi { 1 } else { 0 };
+                    assert_eq!(hits, expected, "{:?} in {:?}..={:?}", key, lo, hi);
+                }
+                assert!(cover.windows(2).all(|w| w[0].bytes() < w[1].bytes()));
+            }
+        }
+    }
+    #[test]
+    fn bound_that_prefixes_the_other() {
+        let cover = range_cover(b"ab", b"abc").unwrap();
+
+        assert_eq!(cover.first(), Some(&LexPrefix::Key(b"ab".to_vec())));
+        assert_eq!(cover.last(), Some(&LexPrefix::Key(b"abc".to_vec())));
+        assert_eq!(cover.len(), 1 + 99 + 1);
+        assert!(cover.contains(&LexPrefix::Prefix(b"abb".to_vec())));
+        assert!(!cover.iter().any(|entry| entry.matches(b"abcd")));
+    }
+    #[test]
+    fn lower_bound_keeps_its_extensions() {
+        let cover = range_cover(b"abc", b"abd").unwrap();
+
+        assert_eq!(cover, vec![
+            LexPrefix::Prefix(b"abc".to_vec()),
+            LexPrefix::Key(b"abd".to_vec()),
+        ]);
+    }
+    #[test]
+    fn single_and_empty_keys() {
+        assert_eq!(range_cover(b"k", b"k").unwrap(), vec![LexPrefix::Key(b"k".to_vec())]);
+        assert_eq!(range_cover(b"", b"").unwrap(), vec![LexPrefix::Key(vec![])]);
+    }
+    #[test]
+    fn rejects_inverted_range() {
+        assert_eq!(range_cover(b"abd", b"abcd"), Err(RangePrefixError::InvertedRange));
+        assert_eq!(range_cover(b"ab", b"a"), Err(RangePrefixError::InvertedRange));
+    }
+}
@@ -2,10 +2,46 @@
 //! The intented use is for making range queries on key-value stores which only accept prefix queries. (e.g. Redis and S3)
 //!
 //! # Parameters and Return Types
-//! The base type that all the functions operate on is a slice of booleans.
+//! The functions at the crate root operate on slices of booleans.
 //! The examples pass array references, but vectors are also compatible.
 //! Each element in the slice represents a binary zero or one.
 //! Prefixes are returned as slices of the original inputs.
+//!
+//! The same functions are implemented on the packed [`BitString`] and [`BitStr`] types
+//! in the [`bits`] module, which pack 64 bits into each word instead of using a byte per bit.
+//! The boolean versions convert to and from these types.
+
+extern crate futures_core;
+
+pub mod bits;
+pub mod bounds;
+pub mod byte_key;
+pub mod cidr;
+mod codec;
+pub mod error;
+pub mod filter;
+pub mod float_key;
+pub mod glob;
+pub mod histogram;
+pub mod int_key;
+pub mod key_range;
+pub mod lex;
+pub mod merge;
+pub mod planner;
+pub mod prefix_set;
+pub mod radix;
+pub mod s3;
+pub mod store;
+pub mod zlex;
+
+pub use bits::{BitStr, BitString};
+pub use bounds::{cover, CoverKey};
+pub use error::RangePrefixError;
+pub use float_key::FloatKey;
+pub use int_key::IntKey;
+pub use prefix_set::PrefixSet;
+
+use error::validate_range;
 
 /// Utility function to pad an input value with leading zeros.
 ///
@@ -18,45 +54,15 @@
 /// // [false, true, false]
 /// ```
 pub fn pad(size: usize, input: &[bool]) -> Vec<bool> {
-    let input_len = input.len();
-    let mut out: Vec<bool> = Vec::new();
-
-    // add padding
-    for _ in input_len..size {
-        out.push(false);
-    }
-
-    // add contents of old vector
-    for item in input.iter() {
-        out.push(item.clone());
-    }
-
+    let mut out = vec![false; size.saturating_sub(input.len())];
+    out.extend_from_slice(input);
     out
 }
 
-fn find_seq(initial: bool, collection: &[bool]) -> usize {
-    let mut final_count = 0;
-    let mut expected_value = initial;
-
-    for entry in collection {
-        // println!("entry: {}, expected: {}", entry, expected_value);
-
-        if *entry == expected_value {
-            final_count += 1;
-        } else {
-            break;
-        }
-
-        if expected_value == initial {
-            expected_value = !initial;
-        }
-    }
-
-    final_count
-}
-
 /// Finds the longest possible shared prefix between two binary vectors.
 ///
+/// See [`BitStr::shared_prefix`] for the packed equivalent.
+///
 /// # Example
 ///
 /// ```
@@ -81,8 +87,20 @@ pub fn shared_prefix<'a>(start: &'a [bool], end: &[bool]) -> &'a [bool] {
     &start[0..slice_end]
 }
 
+/// Counts the leading bits of `collection` that start with `initial` and are followed
+/// by bits equal to `!initial`.
+fn find_seq(initial: bool, collection: &[bool]) -> usize {
+    match collection.split_first() {
+        Some((&first, rest)) if first == initial => 1 + rest.iter().position(|&bit| bit == initial).unwrap_or(rest.len()),
+        _ => 0,
+    }
+}
+
 /// Finds the two longest prefixes that cover a binary range.
 ///
+/// The two prefixes are a heuristic: keys between them can be missed and keys
+/// outside the range can be included. Use [`range_cover`] for an exact cover.
+///
 /// # Example
 ///
 /// ```
@@ -97,24 +115,121 @@ pub fn shared_prefix<'a>(start: &'a [bool], end: &[bool]) -> &'a [bool] {
 /// //     [true, false, true, false, true, false, false, true]
 /// // )
 /// ```
+///
+/// # Panics
+///
+/// Panics if the range is rejected by [`try_range_prefix`].
 pub fn range_prefix<'a, 'b>(start: &'a [bool], end: &'b [bool]) -> (&'a [bool], &'b [bool]) {
+    try_range_prefix(start, end).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Finds the two longest prefixes that cover a binary range, or reports why the range is malformed.
+///
+/// The bounds must be non-empty, have the same length, and `start` must not be greater than `end`.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::{try_range_prefix, RangePrefixError};
+///
+/// let a = vec![true, true];
+/// let b = vec![true, false];
+///
+/// try_range_prefix(&a, &b);
+/// // Err(RangePrefixError::InvertedRange)
+/// ```
+pub fn try_range_prefix<'a, 'b>(
+    start: &'a [bool],
+    end: &'b [bool],
+) -> Result<(&'a [bool], &'b [bool]), RangePrefixError> {
+    validate_range(start, end)?;
 
     let segment_len = end.len();
-    // let start = pad_vec(false, segment_len, start);
 
-    let base = shared_prefix(&start, end);
-    let base_len = base.len();
+    let base_len = shared_prefix(start, end).len();
 
     let start_special = find_seq(false, &start[base_len..segment_len]);
     let end_special = find_seq(true, &end[base_len..segment_len]);
 
-    let start_prefix_len = base_len + start_special;
-    let end_prefix_len   = base_len + end_special;
+    Ok((&start[..base_len + start_special], &end[..base_len + end_special]))
+}
+
+/// Finds the minimal set of prefixes whose union is exactly the inclusive range `[start, end]`.
+///
+/// This is the dyadic decomposition used for range-to-CIDR conversion.
+/// Prefixes are returned in ascending order and never overlap.
+/// The range is validated in the same way as [`try_range_prefix`].
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::range_cover;
+///
+/// let a = vec![false, false, true, true];
+/// let b = vec![true, false, true, false];
+///
+/// range_cover(&a, &b);
+/// // Ok([
+/// //     [false, false, true, true],
+/// //     [false, true],
+/// //     [true, false, false],
+/// //     [true, false, true, false]
+/// // ])
+/// ```
+pub fn range_cover(start: &[bool], end: &[bool]) -> Result<Vec<Vec<bool>>, RangePrefixError> {
+    validate_range(start, end)?;
+
+    let base_len = shared_prefix(start, end).len();
+    if base_len == start.len() {
+        return Ok(vec![start.to_vec()]);
+    }
+
+    let start_tail = start.iter().rposition(|&bit| bit).filter(|&i| i > base_len);
+    let end_tail = end.iter().rposition(|&bit| !bit).filter(|&i| i > base_len);
+    if start_tail.is_none() && end_tail.is_none() {
+        return Ok(vec![start[..base_len].to_vec()]);
+    }
+
+    // the prefix with its last bit flipped
+    let sibling = |prefix: &[bool]| {
+        let mut out = prefix.to_vec();
+        let last = out.len() - 1;
+        out[last] = !out[last];
+        out
+    };
+    let mut cover = Vec::new();
+
+    // lower half: keys under `base + 0` that are not below `start`
+    match start_tail {
+        Some(last_one) => {
+            cover.push(start[..=last_one].to_vec());
+            for i in (base_len + 1..last_one).rev() {
+                if !start[i] {
+                    cover.push(sibling(&start[..=i]));
+                }
+            }
+        }
+        None => cover.push(start[..=base_len].to_vec()),
+    }
+
+    // upper half: keys under `base + 1` that are not above `end`
+    match end_tail {
+        Some(last_zero) => {
+            for i in base_len + 1..last_zero {
+                if end[i] {
+                    cover.push(sibling(&end[..=i]));
+                }
+            }
+            cover.push(end[..=last_zero].to_vec());
+        }
+        None => cover.push(end[..=base_len].to_vec()),
+    }
 
-    (&start[0..start_prefix_len], &end[0..end_prefix_len])
+    Ok(cover)
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod tests {
     use std::fmt::Debug;
     use std::cmp::PartialEq;
@@ -149,6 +264,80 @@ mod tests {
         assert_vec_equal(&start_prefix, result.0);
         assert_vec_equal(&end_prefix, result.1);
     }
+    fn to_bits(value: usize, width: usize) -> Vec<bool> {
+        (0..width).rev().map(|i| value >> i & 1 == 1).collect()
+    }
+    #[test]
+    fn finds_exact_cover() {
+        let start = [false, false, true, true];
+        let end   = [true, false, true, false];
+
+        let result = range_cover(&start, &end).unwrap();
+
+        assert_eq!(result, vec![
+            vec![false, false, true, true],
+            vec![false, true],
+            vec![true, false, false],
+            vec![true, false, true, false],
+        ]);
+    }
+    #[test]
+    fn cover_matches_range_exactly() {
+        let width = 5;
+        for lo in 0..32 {
+            for hi in lo..32 {
+                let (start, end) = (to_bits(lo, width), to_bits(hi, width));
+                let cover = range_cover(&start, &end).unwrap();
+                let (packed_start, packed_end) = (BitString::from(&start[..]), BitString::from(&end[..]));
+                let packed = bits::range_cover(packed_start.as_bit_str(), packed_end.as_bit_str()).unwrap();
+                assert_eq!(cover, packed.iter().map(BitString::to_vec).collect::<Vec<_>>());
+                let (start_prefix, end_prefix) = try_range_prefix(&start, &end).unwrap();
+                let packed = bits::try_range_prefix(packed_start.as_bit_str(), packed_end.as_bit_str()).unwrap();
+                assert_eq!((start_prefix.len(), end_prefix.len()), (packed.0.len(), packed.1.len()));
+                for key in 0..32 {
+                    let bits = to_bits(key, width);
+                    let hits = cover.iter().filter(|p| bits.starts_with(p)).count();
+                    let expected = if lo <= key && key <= hi { 1 } else { 0 };
+                    assert_eq!(hits, expected, "key {} in [{}, {}]", key, lo, hi);
+                }
+                assert!(cover.windows(2).all(|w| w[0] < w[1]));
+                assert!(cover.windows(2).all(|w| {
+                    let (a, b) = (BitString::from(&w[0][..]), BitString::from(&w[1][..]));
+                    bits::sibling(a.as_bit_str()) != b
+                }));
+            }
+        }
+    }
+    #[test]
+    fn cover_of_full_subtree_is_single_prefix() {
+        let result = range_cover(&[true, false, false, false], &[true, false, true, true]).unwrap();
+
+        assert_eq!(result, vec![vec![true, false]]);
+    }
+    #[test]
+    fn cover_of_single_key() {
+        let key = [true, false, true];
+
+        assert_eq!(range_cover(&key, &key), Ok(vec![key.to_vec()]));
+    }
+    #[test]
+    fn rejects_malformed_ranges() {
+        let short = [true];
+        let long = [true, false];
+
+        assert_eq!(try_range_prefix(&short, &long), Err(RangePrefixError::LengthMismatch { start: 1, end: 2 }));
+        assert_eq!(try_range_prefix(&long, &short), Err(RangePrefixError::LengthMismatch { start: 2, end: 1 }));
+        assert_eq!(try_range_prefix(&[true, true], &long), Err(RangePrefixError::InvertedRange));
+        assert_eq!(try_range_prefix(&[], &[]), Err(RangePrefixError::EmptyInput));
+        assert_eq!(range_cover(&long, &short), Err(RangePrefixError::LengthMismatch { start: 2, end: 1 }));
+        assert_eq!(range_cover(&[true, true], &long), Err(RangePrefixError::InvertedRange));
+        assert_eq!(range_cover(&[], &[]), Err(RangePrefixError::EmptyInput));
+    }
+    #[test]
+    #[should_panic(expected = "range start is greater than range end")]
+    fn range_prefix_panics_on_inverted_range() {
+        range_prefix(&[true, true], &[true, false]);
+    }
     #[test]
     fn do_pad_vec() {
         let expected = [false, true, false];
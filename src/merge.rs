@@ -0,0 +1,221 @@
+//! Combining the results of several prefix scans into one ordered stream.
+//!
+//! A cover with several prefixes is scanned with several independent requests. S3
+//! listings come back in key order, so [`merge_sorted`] combines them with a k-way merge
+//! that holds one key per scan. Redis `SCAN` returns keys in no particular order and may
+//! repeat them, so [`merge_unordered`] sorts each scan first, holding at most a given
+//! number of keys.
+//!
+//! Both work with any `Ord` key: byte strings, `BitString`s and the fixed-width
+//! encodings in [`int_key`](../int_key/index.html) and
+//! [`float_key`](../float_key/index.html) all sort in scan order.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap};
+use std::vec;
+
+use error::MergeError;
+
+/// Merges key-ordered streams into one key-ordered stream without duplicates.
+///
+/// Only one key per stream is held at a time.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::merge::merge_sorted;
+///
+/// let scans = vec![vec![1, 4, 9], vec![2, 4], vec![], vec![3, 10]];
+///
+/// merge_sorted(scans).collect::<Vec<_>>();
+/// // [1, 2, 3, 4, 9, 10]
+/// ```
+pub fn merge_sorted<I>(sources: I) -> MergeSorted<<I::Item as IntoIterator>::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: IntoIterator,
+    <I::Item as IntoIterator>::Item: Ord,
+{
+    let mut sources: Vec<_> = sources.into_iter().map(IntoIterator::into_iter).collect();
+    let mut heap = BinaryHeap::with_capacity(sources.len());
+    for (i, source) in sources.iter_mut().enumerate() {
+        if let Some(key) = source.next() {
+            heap.push(Reverse((key, i)));
+        }
+    }
+    MergeSorted {
+        sources,
+        heap,
+        out_of_order: 0,
+    }
+}
+
+/// Sorts unordered streams and merges them into one key-ordered stream without duplicates.
+///
+/// Every key is held in memory until it is yielded. Keys repeated within a stream are
+/// buffered once, and an error is returned if more than `max_buffered` keys would be held.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::merge::merge_unordered;
+///
+/// let scans = vec![vec!["b", "a", "b"], vec!["c", "a"]];
+///
+/// merge_unordered(scans, 100).unwrap().collect::<Vec<_>>();
+/// // ["a", "b", "c"]
+/// ```
+pub fn merge_unordered<I, K>(sources: I, max_buffered: usize) -> Result<MergeSorted<vec::IntoIter<K>>, MergeError>
+where
+    I: IntoIterator,
+    I::Item: IntoIterator<Item = K>,
+    K: Ord,
+{
+    let mut runs = Vec::new();
+    let mut buffered = 0;
+    for source in sources {
+        let mut run = BTreeSet::new();
+        for key in source {
+            if run.contains(&key) {
+                continue;
+            }
+            if buffered + run.len() == max_buffered {
+                return Err(MergeError::BufferFull { limit: max_buffered });
+            }
+            run.insert(key);
+        }
+        buffered += run.len();
+        runs.push(run.into_iter().collect::<Vec<K>>());
+    }
+    Ok(merge_sorted(runs))
+}
+
+/// The iterator returned by [`merge_sorted`] and [`merge_unordered`].
+#[derive(Debug)]
+pub struct MergeSorted<S: Iterator> {
+    sources: Vec<S>,
+    heap: BinaryHeap<Reverse<(S::Item, usize)>>,
+    out_of_order: u64,
+}
+
+impl<S: Iterator> MergeSorted<S>
+where
+    S::Item: Ord,
+{
+    /// Returns the number of times a stream yielded a key smaller than its previous one.
+    ///
+    /// Such keys are still yielded, but the output is no longer in order. Streams that
+    /// are not sorted should be merged with [`merge_unordered`] instead.
+    pub fn out_of_order(&self) -> u64 {
+        self.out_of_order
+    }
+
+    /// Pulls the next key of `source`, which last yielded `prev`, onto the heap.
+    fn refill(&mut self, source: usize, prev: &S::Item) {
+        if let Some(key) = self.sources[source].next() {
+            if key < *prev {
+                self.out_of_order += 1;
+            }
+            self.heap.push(Reverse((key, source)));
+        }
+    }
+}
+
+impl<S: Iterator> Iterator for MergeSorted<S>
+where
+    S::Item: Ord,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        let Reverse((key, source)) = self.heap.pop()?;
+        self.refill(source, &key);
+
+        // every copy of `key` is now at the top of the heap
+        while self.heap.peek().is_some_and(|Reverse((next, _))| *next == key) {
+            let Reverse((_, other)) = self.heap.pop().unwrap();
+            self.refill(other, &key);
+        }
+        Some(key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let lower = if self.heap.is_empty() { 0 } else { 1 };
+        let upper = self
+            .sources
+            .iter()
+            .try_fold(self.heap.len(), |total, source| source.size_hint().1.and_then(|n| total.checked_add(n)));
+        (lower, upper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bits::BitString;
+    use int_key::{self, IntKey};
+    use lex::{self, LexPrefix};
+
+    #[test]
+    fn merges_sorted_scans_of_a_cover() {
+        let keys: Vec<BitString> = (0..1000u16).map(|k| (k * 37).to_key()).collect();
+        let cover = int_key::range_cover(5000u16, 30000u16).unwrap();
+        let scans = cover.iter().map(|p| keys.iter().filter(move |k| k.as_bit_str().starts_with(p.as_bit_str())));
+
+        let mut expected: Vec<&BitString> = keys.iter().filter(|k| (5000u16.to_key()..=30000u16.to_key()).contains(k)).collect();
+        expected.sort();
+
+        assert_eq!(merge_sorted(scans).collect::<Vec<_>>(), expected);
+    }
+    #[test]
+    fn drops_duplicates_within_and_across_scans() {
+        let scans = vec![vec![b"a".to_vec(), b"a".to_vec(), b"c".to_vec()], vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]];
+
+        let merged = merge_sorted(scans);
+
+        assert_eq!(merged.collect::<Vec<_>>(), vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+    }
+    #[test]
+    fn counts_out_of_order_keys() {
+        let mut merged = merge_sorted(vec![vec![1, 5, 3], vec![2]]);
+
+        assert_eq!(merged.by_ref().collect::<Vec<_>>(), vec![1, 2, 5, 3]);
+        assert_eq!(merged.out_of_order(), 1);
+    }
+    #[test]
+    fn sorts_unordered_scans() {
+        // like Redis SCAN: each scan returns its keys shuffled and may repeat them
+        let keys: Vec<&[u8]> = vec![b"k:1", b"k:10", b"k:2", b"k:3", b"k:30", b"k:4"];
+        let cover = lex::range_cover(b"k:10", b"k:3").unwrap();
+        let scans: Vec<Vec<&[u8]>> = cover
+            .iter()
+            .map(|entry: &LexPrefix| {
+                let mut hits: Vec<&[u8]> = keys.iter().cloned().filter(|k| entry.matches(k)).collect();
+                hits.reverse();
+                hits.extend(hits.clone());
+                hits
+            })
+            .collect();
+
+        let merged = merge_unordered(scans, 4).unwrap();
+
+        assert_eq!(merged.collect::<Vec<_>>(), vec![&b"k:10"[..], b"k:2", b"k:3"]);
+    }
+    #[test]
+    fn bounds_buffered_keys() {
+        let scans = vec![vec![3, 1, 3, 1], vec![2, 4, 2]];
+
+        assert!(merge_unordered(scans.clone(), 4).is_ok());
+        assert_eq!(merge_unordered(scans, 3).err(), Some(MergeError::BufferFull { limit: 3 }));
+        assert_eq!(merge_unordered(vec![vec![1]], 0).err(), Some(MergeError::BufferFull { limit: 0 }));
+    }
+    #[test]
+    fn stays_fast_with_a_full_buffer() {
+        // a full buffer of keys, each then repeated many times as a long SCAN may
+        let scan: Vec<u32> = (0..200).flat_map(|_| (0..5000).rev()).collect();
+
+        let merged = merge_unordered(vec![scan], 5000).unwrap();
+
+        assert!(merged.eq(0..5000));
+    }
+}
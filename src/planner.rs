@@ -0,0 +1,262 @@
+//! Prefix covers that fit within a budget of scans.
+//!
+//! An exact cover can need up to two prefixes per bit of the key, and each prefix is a
+//! separate S3 listing or Redis scan. The planner trades exactness for fewer requests:
+//! neighbouring prefixes are merged into their common ancestor (their
+//! [`shared_prefix`](../bits/struct.BitStr.html#method.shared_prefix)), which fetches some
+//! keys outside the range. Of all the ways to merge the cover down to the budget, the
+//! planner picks the one that over-fetches the least.
+//!
+//! How much a merge over-fetches depends on where the keys are. [`plan_cover`] assumes
+//! they are spread evenly over the key space; [`plan_cover_with`] takes a
+//! [`KeyDistribution`], such as a [`KeyHistogram`](../histogram/struct.KeyHistogram.html)
+//! sampled from the real keys.
+
+use bits::{self, BitStr, BitString};
+use error::RangePrefixError;
+
+/// A cover that fits within a budget, along with how much it over-fetches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoverPlan {
+    /// The prefixes to scan, in ascending order.
+    pub prefixes: Vec<BitString>,
+    /// The expected share of all keys matched by `prefixes`.
+    pub fetched: f64,
+    /// The expected share of all keys matched by `prefixes` but outside the range.
+    pub wasted: f64,
+}
+
+impl CoverPlan {
+    /// Returns the fraction of the fetched keys expected to lie outside the range.
+    ///
+    /// This is zero for an exact cover.
+    pub fn overfetch(&self) -> f64 {
+        if self.fetched > 0.0 {
+            self.wasted / self.fetched
+        } else {
+            0.0
+        }
+    }
+}
+
+/// How keys are spread over the key space.
+pub trait KeyDistribution {
+    /// Returns the expected share of all keys that start with `prefix`.
+    ///
+    /// The weight of a prefix should equal the total weight of its two children.
+    fn weight(&self, prefix: BitStr) -> f64;
+}
+
+/// Keys spread evenly over the key space, so a prefix of length `n` holds `2^-n` of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UniformKeys;
+
+impl KeyDistribution for UniformKeys {
+    fn weight(&self, prefix: BitStr) -> f64 {
+        0.5f64.powi(prefix.len() as i32)
+    }
+}
+
+/// Merges an exact cover down to at most `max_prefixes` prefixes with the least over-fetch,
+/// assuming keys are spread evenly.
+///
+/// `cover` must be sorted and free of overlaps, as returned by the `range_cover` functions.
+/// A budget of zero is treated as a budget of one.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::int_key::range_cover;
+/// use binary_prefix::planner::plan_cover;
+///
+/// let cover = range_cover(3u8, 12u8).unwrap();
+/// let plan = plan_cover(&cover, 3);
+///
+/// &plan.prefixes;
+/// // [00000, 000010, 00001100]
+/// plan.overfetch();
+/// // 0.2307... (3 of the 13 fetched keys are outside the range)
+/// ```
+pub fn plan_cover(cover: &[BitString], max_prefixes: usize) -> CoverPlan {
+    plan_cover_with(cover, max_prefixes, &UniformKeys)
+}
+
+/// Finds the exact cover of `[start, end]` and merges it down to at most `max_prefixes` prefixes.
+///
+/// See [`plan_cover`].
+pub fn plan_range(start: BitStr, end: BitStr, max_prefixes: usize) -> Result<CoverPlan, RangePrefixError> {
+    let cover = bits::range_cover(start, end)?;
+    Ok(plan_cover(&cover, max_prefixes))
+}
+
+/// Merges an exact cover down to at most `max_prefixes` prefixes, minimizing the expected
+/// share of keys fetched from outside the range under `keys`.
+///
+/// See [`plan_cover`].
+pub fn plan_cover_with<D: KeyDistribution>(cover: &[BitString], max_prefixes: usize, keys: &D) -> CoverPlan {
+    if cover.is_empty() {
+        return CoverPlan {
+            prefixes: Vec::new(),
+            fetched: 0.0,
+            wasted: 0.0,
+        };
+    }
+
+    let budget = max_prefixes.clamp(1, cover.len());
+    let mut tree = Tree {
+        nodes: Vec::new(),
+        budget,
+    };
+    let root = tree.build(cover, keys);
+
+    let mut prefixes = Vec::new();
+    tree.collect(root, budget, &mut prefixes);
+
+    let fetched = prefixes.iter().map(|p| keys.weight(p.as_bit_str())).sum();
+    CoverPlan {
+        prefixes,
+        fetched,
+        wasted: tree.nodes[root].best[budget - 1].0,
+    }
+}
+
+/// How a node spends its budget.
+#[derive(Clone, Copy)]
+enum Choice {
+    /// Scan the node's prefix.
+    Merge,
+    /// Give this much budget to the left child and the rest to the right.
+    Split(usize),
+}
+
+/// A node of the trie over the cover, compressed to the branching points.
+struct Node {
+    prefix: BitString,
+    children: Option<(usize, usize)>,
+    /// `best[j]` is the least waste and how to reach it with at most `j + 1` prefixes.
+    best: Vec<(f64, Choice)>,
+}
+
+struct Tree {
+    nodes: Vec<Node>,
+    budget: usize,
+}
+
+impl Tree {
+    /// Builds the subtree over `items` and solves it, returning its index.
+    fn build<D: KeyDistribution>(&mut self, items: &[BitString], keys: &D) -> usize {
+        if items.len() == 1 {
+            return self.push(Node {
+                prefix: items[0].clone(),
+                children: None,
+                best: vec![(0.0, Choice::Merge); self.budget],
+            });
+        }
+
+        // items are disjoint, so all of them are longer than their shared prefix
+        let ancestor = items[0].as_bit_str().shared_prefix(items[items.len() - 1].as_bit_str());
+        let split = items.partition_point(|p| !p[ancestor.len()]);
+        let left = self.build(&items[..split], keys);
+        let right = self.build(&items[split..], keys);
+
+        let exact: f64 = items.iter().map(|p| keys.weight(p.as_bit_str())).sum();
+        let merged = (keys.weight(ancestor) - exact).max(0.0);
+
+        let mut best = vec![(merged, Choice::Merge); self.budget];
+        for (j, slot) in best.iter_mut().enumerate().skip(1) {
+            // `j + 1` prefixes in total, at least one on each side
+            for a in 1..=j {
+                let waste = self.nodes[left].best[a - 1].0 + self.nodes[right].best[j - a].0;
+                if waste < slot.0 {
+                    *slot = (waste, Choice::Split(a));
+                }
+            }
+        }
+
+        self.push(Node {
+            prefix: ancestor.to_bit_string(),
+            children: Some((left, right)),
+            best,
+        })
+    }
+
+    fn push(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Pushes the prefixes chosen for `node` with `budget` prefixes onto `out`.
+    fn collect(&self, node: usize, budget: usize, out: &mut Vec<BitString>) {
+        let node = &self.nodes[node];
+        match (node.best[budget - 1].1, node.children) {
+            (Choice::Split(a), Some((left, right))) => {
+                self.collect(left, a, out);
+                self.collect(right, budget - a, out);
+            }
+            _ => out.push(node.prefix.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use int_key;
+
+    fn bits(s: &str) -> BitString {
+        s.chars().map(|c| c == '1').collect()
+    }
+
+    #[test]
+    fn keeps_exact_cover_within_budget() {
+        let cover = int_key::range_cover(3u8, 12u8).unwrap();
+
+        let plan = plan_cover(&cover, 10);
+
+        assert_eq!(plan.prefixes, cover);
+        assert_eq!(plan.wasted, 0.0);
+        assert_eq!(plan.overfetch(), 0.0);
+    }
+    #[test]
+    fn merges_with_least_overfetch() {
+        let cover = int_key::range_cover(3u8, 12u8).unwrap();
+
+        let plan = plan_cover(&cover, 3);
+
+        assert_eq!(plan.prefixes, vec![bits("00000"), bits("000010"), bits("00001100")]);
+        assert_eq!(plan.wasted, 3.0 / 256.0);
+        assert_eq!(plan.fetched, 13.0 / 256.0);
+    }
+    #[test]
+    fn single_prefix_is_the_common_ancestor() {
+        let cover = int_key::range_cover(3u8, 12u8).unwrap();
+
+        let plan = plan_cover(&cover, 0);
+
+        assert_eq!(plan.prefixes, vec![bits("0000")]);
+        assert_eq!(plan.overfetch(), 6.0 / 16.0);
+    }
+    #[test]
+    fn budget_never_increases_waste() {
+        let cover = int_key::range_cover(1234u16, 50000u16).unwrap();
+        let mut last = 1.0;
+        for budget in 1..=cover.len() {
+            let plan = plan_cover(&cover, budget);
+            assert!(plan.prefixes.len() <= budget);
+            assert!(plan.wasted <= last);
+            assert!(plan.prefixes.windows(2).all(|w| w[0] < w[1]));
+            last = plan.wasted;
+        }
+        assert_eq!(last, 0.0);
+    }
+    #[test]
+    fn plans_ranges() {
+        let (start, end) = (bits("0011"), bits("1010"));
+
+        let plan = plan_range(start.as_bit_str(), end.as_bit_str(), 1).unwrap();
+
+        assert_eq!(plan.prefixes, vec![BitString::new()]);
+        assert_eq!(plan_range(end.as_bit_str(), start.as_bit_str(), 1), Err(RangePrefixError::InvertedRange));
+        assert_eq!(plan_cover(&[], 3).prefixes, Vec::<BitString>::new());
+    }
+}
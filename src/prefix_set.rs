@@ -0,0 +1,282 @@
+//! Sets of keys described by bit prefixes.
+//!
+//! A [`PrefixSet`] holds the keys matched by any of its prefixes. It is always kept in
+//! canonical form: prefixes are sorted, none covers another, and no two siblings are
+//! present (they are merged into their parent). Two sets hold the same keys exactly when
+//! they compare equal.
+
+use std::iter::FromIterator;
+use std::slice;
+
+use bits::{self, BitStr, BitString};
+
+/// A canonical set of bit prefixes.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::cidr::Ipv4Net;
+/// use binary_prefix::{BitString, PrefixSet};
+///
+/// let allow: PrefixSet = ["10.0.0.0/25", "10.0.0.128/25", "10.0.0.7/32"]
+///     .iter()
+///     .map(|net| BitString::from(net.parse::<Ipv4Net>().unwrap()))
+///     .collect();
+///
+/// allow.iter().map(|p| Ipv4Net::from_prefix(p.as_bit_str()).unwrap().to_string()).collect::<Vec<_>>();
+/// // ["10.0.0.0/24"]
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PrefixSet {
+    prefixes: Vec<BitString>,
+}
+
+impl PrefixSet {
+    /// Creates an empty set.
+    pub fn new() -> PrefixSet {
+        PrefixSet::default()
+    }
+
+    /// Returns the number of prefixes in canonical form.
+    pub fn len(&self) -> usize {
+        self.prefixes.len()
+    }
+
+    /// Returns `true` if the set matches no keys.
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    /// Returns an iterator over the prefixes in ascending order.
+    pub fn iter(&self) -> slice::Iter<'_, BitString> {
+        self.prefixes.iter()
+    }
+
+    /// Returns the prefixes in ascending order.
+    pub fn as_slice(&self) -> &[BitString] {
+        &self.prefixes
+    }
+
+    /// Adds every key matched by `prefix`.
+    pub fn insert(&mut self, prefix: BitString) {
+        self.prefixes.push(prefix);
+        let prefixes = self.prefixes.split_off(0);
+        self.prefixes = canonicalize(prefixes);
+    }
+
+    /// Returns `true` if every key matched by `prefix` is in the set.
+    ///
+    /// Passing a full-length key answers whether that key is in the set.
+    pub fn contains(&self, prefix: BitStr) -> bool {
+        self.covering(prefix).is_some()
+    }
+
+    /// Returns the set of keys in either set.
+    pub fn union(&self, other: &PrefixSet) -> PrefixSet {
+        self.iter().chain(other.iter()).cloned().collect()
+    }
+
+    /// Returns the set of keys in both sets.
+    pub fn intersection(&self, other: &PrefixSet) -> PrefixSet {
+        let (mut i, mut j) = (0, 0);
+        let mut out = Vec::new();
+        while i < self.len() && j < other.len() {
+            let (a, b) = (self.prefixes[i].as_bit_str(), other.prefixes[j].as_bit_str());
+            if a.starts_with(b) {
+                out.push(a.to_bit_string());
+                i += 1;
+            } else if b.starts_with(a) {
+                out.push(b.to_bit_string());
+                j += 1;
+            } else if a < b {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        PrefixSet {
+            prefixes: canonicalize(out),
+        }
+    }
+
+    /// Returns the set of keys in this set but not in `other`.
+    pub fn difference(&self, other: &PrefixSet) -> PrefixSet {
+        let mut out = Vec::new();
+        for prefix in self.iter() {
+            let prefix = prefix.as_bit_str();
+            if other.contains(prefix) {
+                continue;
+            }
+            subtract(prefix, other.descendants(prefix), &mut out);
+        }
+        PrefixSet {
+            prefixes: canonicalize(out),
+        }
+    }
+
+    /// Returns the prefix in the set that covers `prefix`, if any.
+    fn covering(&self, prefix: BitStr) -> Option<&BitString> {
+        // an ancestor sorts immediately before its descendants, and the set is disjoint
+        let index = self.prefixes.partition_point(|p| p.as_bit_str() <= prefix);
+        self.prefixes[..index]
+            .last()
+            .filter(|p| prefix.starts_with(p.as_bit_str()))
+    }
+
+    /// Returns the prefixes in the set that are strictly inside `prefix`.
+    fn descendants(&self, prefix: BitStr) -> &[BitString] {
+        let start = self.prefixes.partition_point(|p| p.as_bit_str() <= prefix);
+        let len = self.prefixes[start..]
+            .iter()
+            .take_while(|p| p.as_bit_str().starts_with(prefix))
+            .count();
+        &self.prefixes[start..start + len]
+    }
+}
+
+/// Pushes the parts of `prefix` not covered by `holes` onto `out`.
+///
+/// `holes` must be sorted, disjoint and strictly inside `prefix`.
+fn subtract(prefix: BitStr, holes: &[BitString], out: &mut Vec<BitString>) {
+    if holes.is_empty() {
+        out.push(prefix.to_bit_string());
+        return;
+    }
+    if holes.iter().any(|hole| hole.len() == prefix.len()) {
+        return;
+    }
+    let split = holes.partition_point(|hole| !hole[prefix.len()]);
+    let mut zero = prefix.to_bit_string();
+    zero.push(false);
+    let one = bits::sibling(zero.as_bit_str());
+    subtract(zero.as_bit_str(), &holes[..split], out);
+    subtract(one.as_bit_str(), &holes[split..], out);
+}
+
+/// Sorts `prefixes`, drops covered entries and merges siblings into their parents.
+fn canonicalize(mut prefixes: Vec<BitString>) -> Vec<BitString> {
+    prefixes.sort();
+
+    let mut out: Vec<BitString> = Vec::with_capacity(prefixes.len());
+    for prefix in prefixes {
+        if out.last().is_some_and(|last| prefix.as_bit_str().starts_with(last.as_bit_str())) {
+            continue;
+        }
+        out.push(prefix);
+        while out.len() >= 2 {
+            let last = &out[out.len() - 1];
+            if last.is_empty() || bits::sibling(last.as_bit_str()) != out[out.len() - 2] {
+                break;
+            }
+            out.pop();
+            let parent = out.last_mut().unwrap();
+            let len = parent.len() - 1;
+            parent.truncate(len);
+        }
+    }
+    out
+}
+
+impl FromIterator<BitString> for PrefixSet {
+    fn from_iter<I: IntoIterator<Item = BitString>>(iter: I) -> PrefixSet {
+        PrefixSet {
+            prefixes: canonicalize(iter.into_iter().collect()),
+        }
+    }
+}
+
+impl Extend<BitString> for PrefixSet {
+    fn extend<I: IntoIterator<Item = BitString>>(&mut self, iter: I) {
+        let mut prefixes = self.prefixes.split_off(0);
+        prefixes.extend(iter);
+        self.prefixes = canonicalize(prefixes);
+    }
+}
+
+impl IntoIterator for PrefixSet {
+    type Item = BitString;
+    type IntoIter = ::std::vec::IntoIter<BitString>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.prefixes.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a PrefixSet {
+    type Item = &'a BitString;
+    type IntoIter = slice::Iter<'a, BitString>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.prefixes.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(s: &str) -> BitString {
+        s.chars().map(|c| c == '1').collect()
+    }
+
+    fn set(prefixes: &[&str]) -> PrefixSet {
+        prefixes.iter().map(|p| bits(p)).collect()
+    }
+
+    fn keys(set: &PrefixSet, width: usize) -> Vec<usize> {
+        (0..1 << width)
+            .filter(|k| {
+                let key: BitString = (0..width).rev().map(|i| k >> i & 1 == 1).collect();
+                set.contains(key.as_bit_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn canonicalizes() {
+        assert_eq!(set(&["01", "00", "011", "10"]), set(&["0", "10"]));
+        assert_eq!(set(&["110", "111", "10"]), set(&["1"]));
+        assert_eq!(set(&["0", "1"]), set(&[""]));
+        assert_eq!(set(&["0", "1"]).len(), 1);
+        assert_eq!(set(&["001", "01"]).as_slice().len(), 2);
+    }
+    #[test]
+    fn answers_containment() {
+        let s = set(&["001", "1"]);
+
+        assert!(s.contains(bits("0010").as_bit_str()));
+        assert!(s.contains(bits("001").as_bit_str()));
+        assert!(s.contains(bits("111").as_bit_str()));
+        assert!(!s.contains(bits("00").as_bit_str()));
+        assert!(!s.contains(bits("0111").as_bit_str()));
+    }
+    #[test]
+    fn set_operations_match_key_sets() {
+        let samples = [
+            set(&[]),
+            set(&[""]),
+            set(&["0010", "01", "1101"]),
+            set(&["00", "011", "111"]),
+            set(&["0101", "1"]),
+        ];
+        for a in &samples {
+            for b in &samples {
+                let (ka, kb) = (keys(a, 4), keys(b, 4));
+
+                let union: Vec<usize> = (0..16).filter(|k| ka.contains(k) || kb.contains(k)).collect();
+                let both: Vec<usize> = (0..16).filter(|k| ka.contains(k) && kb.contains(k)).collect();
+                let only: Vec<usize> = (0..16).filter(|k| ka.contains(k) && !kb.contains(k)).collect();
+
+                assert_eq!(keys(&a.union(b), 4), union);
+                assert_eq!(keys(&a.intersection(b), 4), both);
+                assert_eq!(keys(&a.difference(b), 4), only);
+                assert_eq!(a.difference(b), a.difference(&a.intersection(b)));
+            }
+        }
+    }
+    #[test]
+    fn difference_splits_prefixes() {
+        assert_eq!(set(&["0"]).difference(&set(&["001"])), set(&["000", "01"]));
+        assert_eq!(set(&[""]).difference(&set(&["1"])), set(&["0"]));
+    }
+}
@@ -0,0 +1,225 @@
+//! Prefix covers over arbitrary ordered alphabets.
+//!
+//! Keys such as decimal timestamps or hex IDs are strings of digits in some radix.
+//! Covering their ranges in base 2 is wrong, because a bit prefix does not line up with
+//! a character prefix. An [`Alphabet`] gives each symbol a digit value (its position in
+//! the alphabet), and covers ranges of fixed-width keys with string prefixes.
+
+use error::{validate_range, AlphabetError, RangePrefixError};
+
+/// An ordered set of symbols, each standing for the digit value of its position.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::radix::Alphabet;
+///
+/// Alphabet::decimal().range_cover("0385", "1204");
+/// // Ok(["0385", "0386", "0387", "0388", "0389", "039", "04", "05", "06", "07", "08", "09",
+/// //     "10", "11", "1200", "1201", "1202", "1203", "1204"])
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Alphabet {
+    symbols: Vec<char>,
+}
+
+impl Alphabet {
+    /// Creates an alphabet from its symbols, listed from lowest to highest.
+    pub fn new(symbols: &str) -> Result<Alphabet, AlphabetError> {
+        let symbols: Vec<char> = symbols.chars().collect();
+        if symbols.len() < 2 {
+            return Err(AlphabetError::TooShort);
+        }
+        for (i, symbol) in symbols.iter().enumerate() {
+            if symbols[..i].contains(symbol) {
+                return Err(AlphabetError::DuplicateSymbol(*symbol));
+            }
+        }
+        Ok(Alphabet { symbols })
+    }
+
+    /// The digits `0` to `9`.
+    pub fn decimal() -> Alphabet {
+        Alphabet::new("0123456789").unwrap()
+    }
+
+    /// The digits `0` to `9` followed by the lowercase letters `a` to `f`.
+    pub fn hex() -> Alphabet {
+        Alphabet::new("0123456789abcdef").unwrap()
+    }
+
+    /// The digits `0` to `9` followed by the lowercase letters `a` to `z`.
+    pub fn base36() -> Alphabet {
+        Alphabet::new("0123456789abcdefghijklmnopqrstuvwxyz").unwrap()
+    }
+
+    /// The "Extended Hex" alphabet of RFC 4648: `0` to `9` followed by `A` to `V`.
+    pub fn base32hex() -> Alphabet {
+        Alphabet::new("0123456789ABCDEFGHIJKLMNOPQRSTUV").unwrap()
+    }
+
+    /// Returns the number of symbols.
+    pub fn radix(&self) -> usize {
+        self.symbols.len()
+    }
+
+    /// Returns the digit value of `symbol`, or `None` if it is not in the alphabet.
+    pub fn digit(&self, symbol: char) -> Option<usize> {
+        self.symbols.iter().position(|&s| s == symbol)
+    }
+
+    /// Returns the symbol for a digit value, or `None` if it is not less than the radix.
+    pub fn symbol(&self, digit: usize) -> Option<char> {
+        self.symbols.get(digit).cloned()
+    }
+
+    /// Finds the longest prefix shared by the fixed-width keys in `[lo, hi]`, as a slice of `lo`.
+    ///
+    /// The keys are checked like the bounds of [`range_cover`](#method.range_cover).
+    pub fn shared_prefix<'a>(&self, lo: &'a str, hi: &str) -> Result<&'a str, RangePrefixError> {
+        let lo_digits = self.digits(lo)?;
+        let hi_digits = self.digits(hi)?;
+        validate_range(&lo_digits[..], &hi_digits[..])?;
+
+        let shared = lo_digits.iter().zip(&hi_digits).take_while(|&(a, b)| a == b).count();
+        Ok(lo.char_indices().nth(shared).map_or(lo, |(i, _)| &lo[..i]))
+    }
+
+    /// Finds the minimal set of string prefixes covering the fixed-width keys in `[lo, hi]`.
+    ///
+    /// Keys are ordered digit by digit using the alphabet's order. Prefixes are returned in
+    /// ascending order and never overlap.
+    pub fn range_cover(&self, lo: &str, hi: &str) -> Result<Vec<String>, RangePrefixError> {
+        let lo = self.digits(lo)?;
+        let hi = self.digits(hi)?;
+        validate_range(&lo[..], &hi[..])?;
+
+        let max = self.radix() - 1;
+        let base_len = lo.iter().zip(&hi).take_while(|&(a, b)| a == b).count();
+        if base_len == lo.len() {
+            return Ok(vec![self.render(&lo, &[])]);
+        }
+
+        let lo_tail = lo.iter().rposition(|&d| d != 0).filter(|&i| i > base_len);
+        let hi_tail = hi.iter().rposition(|&d| d != max).filter(|&i| i > base_len);
+
+        let mid_start = if lo_tail.is_some() { lo[base_len] + 1 } else { lo[base_len] };
+        let mid_end = if hi_tail.is_some() { hi[base_len] } else { hi[base_len] + 1 };
+        if mid_start == 0 && mid_end == self.radix() {
+            return Ok(vec![self.render(&lo[..base_len], &[])]);
+        }
+
+        let mut cover = Vec::new();
+
+        // lower part: keys under `base + lo[base_len]` that are not below `lo`
+        if let Some(last) = lo_tail {
+            cover.push(self.render(&lo[..=last], &[]));
+            for i in (base_len + 1..=last).rev() {
+                for d in lo[i] + 1..self.radix() {
+                    cover.push(self.render(&lo[..i], &[d]));
+                }
+            }
+        }
+
+        // middle part: whole subtrees strictly between the bounds
+        for d in mid_start..mid_end {
+            cover.push(self.render(&lo[..base_len], &[d]));
+        }
+
+        // upper part: keys under `base + hi[base_len]` that are not above `hi`
+        if let Some(last) = hi_tail {
+            for i in base_len + 1..=last {
+                for d in 0..hi[i] {
+                    cover.push(self.render(&hi[..i], &[d]));
+                }
+            }
+            cover.push(self.render(&hi[..=last], &[]));
+        }
+
+        Ok(cover)
+    }
+
+    fn digits(&self, key: &str) -> Result<Vec<usize>, RangePrefixError> {
+        key.chars()
+            .map(|c| self.digit(c).ok_or(RangePrefixError::InvalidSymbol(c)))
+            .collect()
+    }
+
+    fn render(&self, head: &[usize], tail: &[usize]) -> String {
+        head.iter().chain(tail).map(|&d| self.symbols[d]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(alphabet: &Alphabet, mut value: usize, width: usize) -> String {
+        let mut digits = vec![0; width];
+        for d in digits.iter_mut().rev() {
+            *d = value % alphabet.radix();
+            value /= alphabet.radix();
+        }
+        alphabet.render(&digits, &[])
+    }
+
+    #[test]
+    fn covers_exactly_in_any_radix() {
+        for alphabet in &[Alphabet::new("ab").unwrap(), Alphabet::new("xyz").unwrap(), Alphabet::new("0123").unwrap()] {
+            let width = 3;
+            let count = alphabet.radix().pow(width as u32);
+            for lo in 0..count {
+                for hi in lo..count {
+                    let cover = alphabet.range_cover(&key(alphabet, lo, width), &key(alphabet, hi, width)).unwrap();
+                    for k in 0..count {
+                        let text = key(alphabet, k, width);
+                        let hits = cover.iter().filter(|p| text.starts_with(p.as_str())).count();
+                        assert_eq!(hits, if lo <= k && k <= hi { 1 } else { 0 });
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    fn matches_binary_cover() {
+        let binary = Alphabet::new("01").unwrap();
+        for lo in 0..32 {
+            for hi in lo..32 {
+                let (a, b) = (key(&binary, lo, 5), key(&binary, hi, 5));
+                let bits = |s: &str| s.chars().map(|c| c == '1').collect::<Vec<bool>>();
+                let expected: Vec<String> = ::range_cover(&bits(&a), &bits(&b))
+                    .unwrap()
+                    .iter()
+                    .map(|p| p.iter().map(|&b| if b { '1' } else { '0' }).collect())
+                    .collect();
+
+                assert_eq!(binary.range_cover(&a, &b).unwrap(), expected);
+            }
+        }
+    }
+    #[test]
+    fn covers_timestamps_and_ids() {
+        assert_eq!(Alphabet::decimal().range_cover("2017000000", "2017999999").unwrap(), vec!["2017"]);
+        assert_eq!(Alphabet::hex().range_cover("00", "ff").unwrap(), vec![""]);
+        assert_eq!(Alphabet::hex().range_cover("3a", "41").unwrap(), vec!["3a", "3b", "3c", "3d", "3e", "3f", "40", "41"]);
+        assert_eq!(Alphabet::base32hex().range_cover("A0", "BV").unwrap(), vec!["A", "B"]);
+        assert_eq!(Alphabet::base36().range_cover("y", "z").unwrap(), vec!["y", "z"]);
+    }
+    #[test]
+    fn finds_shared_prefix() {
+        assert_eq!(Alphabet::decimal().shared_prefix("20170830", "20170901"), Ok("20170"));
+        assert_eq!(Alphabet::new("αβγ").unwrap().shared_prefix("αβα", "αβγ"), Ok("αβ"));
+        assert_eq!(Alphabet::hex().shared_prefix("3a", "3a"), Ok("3a"));
+        assert_eq!(Alphabet::hex().shared_prefix("3a", "3g"), Err(RangePrefixError::InvalidSymbol('g')));
+        assert_eq!(Alphabet::hex().shared_prefix("3b", "3a"), Err(RangePrefixError::InvertedRange));
+    }
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(Alphabet::new("a"), Err(AlphabetError::TooShort));
+        assert_eq!(Alphabet::new("aba"), Err(AlphabetError::DuplicateSymbol('a')));
+        assert_eq!(Alphabet::hex().range_cover("0g", "ff"), Err(RangePrefixError::InvalidSymbol('g')));
+        assert_eq!(Alphabet::hex().range_cover("f", "0"), Err(RangePrefixError::InvertedRange));
+        assert_eq!(Alphabet::hex().range_cover("0", "00"), Err(RangePrefixError::LengthMismatch { start: 1, end: 2 }));
+        assert_eq!(Alphabet::hex().range_cover("", ""), Err(RangePrefixError::EmptyInput));
+    }
+}
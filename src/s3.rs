@@ -0,0 +1,425 @@
+//! `ListObjectsV2` requests for key ranges.
+//!
+//! S3 lists keys in UTF-8 byte order, filtered by a `Prefix` and starting after an
+//! optional `StartAfter` key, but it has no end parameter. A range is split into runs of
+//! keys, each listed with one request: its `Prefix` is the longest one shared by the run,
+//! `StartAfter` skips most keys below the run, and the client stops paginating once a key
+//! passes the
+//! [end](struct.ListObjectsRequest.html#method.is_past_end). `StartAfter` is kept short,
+//! so a few keys right below the range may be listed; [`ListObjectsRequest::contains`]
+//! drops them.
+//!
+//! Without a `Delimiter` the whole range is one run, so it is listed with one request.
+//! With one, S3 rolls every key below the next delimiter up into a single `CommonPrefixes`
+//! entry, and the range is split at the directories its bounds pass through. A range of a layout such as
+//! `year/month/day/` then takes one request for the days left in the first month, one for
+//! the months in between, each returned as a single entry, and one for the days of the
+//! last month. [`ListObjectsRequest::descend`] plans the listing of such an entry.
+//!
+//! No requests are sent; a client executes the plan.
+
+use std::ops::{Bound, RangeBounds};
+
+use error::RangePrefixError;
+use lex;
+
+/// The longest key S3 accepts, in bytes of UTF-8.
+pub const MAX_KEY_LEN: usize = 1024;
+
+/// The parameters of one `ListObjectsV2` request, and where to stop paginating it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ListObjectsRequest {
+    /// The `Prefix` parameter.
+    pub prefix: String,
+    /// The `StartAfter` parameter.
+    pub start_after: Option<String>,
+    /// The `MaxKeys` parameter.
+    pub max_keys: Option<u32>,
+    /// The `Delimiter` parameter.
+    pub delimiter: Option<String>,
+    /// The start of the range, which the client enforces on the keys `StartAfter` lets through.
+    pub start: Bound<String>,
+    /// The end of the range, which the client enforces since S3 has no parameter for it.
+    pub end: Bound<String>,
+}
+
+impl ListObjectsRequest {
+    /// Plans a request for the keys starting with `prefix` between `start` and `end`.
+    ///
+    /// Bounds that every key starting with `prefix` satisfies are dropped.
+    fn new(prefix: String, start: Bound<String>, end: Bound<String>, max_keys: Option<u32>, delimiter: Option<String>) -> ListObjectsRequest {
+        let start = match start {
+            Bound::Included(ref lo) if *lo <= prefix => Bound::Unbounded,
+            Bound::Excluded(ref lo) if *lo < prefix => Bound::Unbounded,
+            start => start,
+        };
+        let start_after = match start {
+            Bound::Included(ref lo) => predecessor(lo),
+            Bound::Excluded(ref lo) => Some(lo.clone()),
+            Bound::Unbounded => None,
+        };
+        let next = lex::prefix_successor(prefix.as_bytes());
+        let end = match end {
+            Bound::Included(ref hi) | Bound::Excluded(ref hi) if next.as_ref().is_some_and(|next| hi.as_bytes() >= &next[..]) => {
+                Bound::Unbounded
+            }
+            end => end,
+        };
+
+        ListObjectsRequest {
+            prefix,
+            start_after,
+            max_keys,
+            delimiter,
+            start,
+            end,
+        }
+    }
+
+    /// Returns `true` if `key` sorts after the range.
+    ///
+    /// Results come back in order, so the client can stop paginating at the first key or
+    /// common prefix past the end.
+    pub fn is_past_end(&self, key: &str) -> bool {
+        match self.end {
+            Bound::Included(ref end) => key > end.as_str(),
+            Bound::Excluded(ref end) => key >= end.as_str(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    /// Returns `true` if `key` is within the range listed by this request.
+    pub fn contains(&self, key: &str) -> bool {
+        let start = match self.start {
+            Bound::Included(ref lo) => key >= lo.as_str(),
+            Bound::Excluded(ref lo) => key > lo.as_str(),
+            Bound::Unbounded => true,
+        };
+        key.starts_with(&self.prefix) && start && !self.is_past_end(key)
+    }
+
+    /// Plans the listing of one of the `CommonPrefixes` returned by this request.
+    ///
+    /// Returns `None` if no key starting with `common_prefix` is within the range. A
+    /// directory that lies wholly inside the range is listed without `StartAfter` or an end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use binary_prefix::s3::plan_range;
+    /// use std::ops::Bound;
+    ///
+    /// let plan = plan_range("2017/03/15/".."2017/05/02/", Some(1000), Some("/")).unwrap();
+    /// let april = plan[1].descend("2017/04/").unwrap();
+    ///
+    /// (april.prefix, april.start_after, april.end);
+    /// // ("2017/04/", None, Unbounded)
+    /// ```
+    pub fn descend(&self, common_prefix: &str) -> Option<ListObjectsRequest> {
+        let next = lex::prefix_successor(common_prefix.as_bytes());
+        if let Bound::Included(ref lo) | Bound::Excluded(ref lo) = self.start {
+            // every key starting with the common prefix is below the start
+            if next.as_ref().is_some_and(|next| lo.as_bytes() >= &next[..]) {
+                return None;
+            }
+        }
+        if self.is_past_end(common_prefix) {
+            return None;
+        }
+
+        Some(ListObjectsRequest::new(
+            common_prefix.to_string(),
+            self.start.clone(),
+            self.end.clone(),
+            self.max_keys,
+            self.delimiter.clone(),
+        ))
+    }
+}
+
+/// Plans the `ListObjectsV2` requests that list the keys in `range`.
+///
+/// A range holding no keys needs no requests, and a range whose start is greater than its
+/// end is rejected with [`RangePrefixError::InvertedRange`]. Otherwise the range is listed
+/// with one request, or with a `delimiter`, one request per directory the start passes
+/// through below the prefix shared by the bounds, one for the keys between the bounds'
+/// directories, and one per directory the end passes through. These are the entries of the
+/// range's [cover](../lex/fn.range_cover.html) grouped by directory, found from the bounds
+/// alone. Requests list disjoint parts of the range, in ascending order.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::s3::plan_range;
+///
+/// let plan = plan_range("logs/2017-03-15".."logs/2017-06", Some(500), None).unwrap();
+///
+/// (&plan[0].prefix, &plan[0].start_after);
+/// // ("logs/2017-0", Some("logs/2017-03-14"))
+///
+/// let plan = plan_range("2017/03/15/"..="2017/05/02/", Some(500), Some("/")).unwrap();
+///
+/// plan.iter().map(|request| &request.prefix).collect::<Vec<_>>();
+/// // ["2017/03/", "2017/0", "2017/05/"]
+/// ```
+pub fn plan_range<K: AsRef<str>, R: RangeBounds<K>>(
+    range: R,
+    max_keys: Option<u32>,
+    delimiter: Option<&str>,
+) -> Result<Vec<ListObjectsRequest>, RangePrefixError> {
+    let start = range.start_bound().map(|k| k.as_ref().to_string());
+    let end = range.end_bound().map(|k| k.as_ref().to_string());
+    if let (Bound::Included(lo) | Bound::Excluded(lo), Bound::Included(hi) | Bound::Excluded(hi)) = (&start, &end) {
+        if lo > hi {
+            return Err(RangePrefixError::InvertedRange);
+        }
+    }
+    if is_empty(&start, &end) {
+        return Ok(Vec::new());
+    }
+
+    // the smallest key in the range
+    let lo = match start {
+        Bound::Included(ref lo) => lo.clone(),
+        Bound::Excluded(ref lo) => format!("{}\0", lo),
+        Bound::Unbounded => String::new(),
+    };
+    let hi = match end {
+        Bound::Included(ref hi) | Bound::Excluded(ref hi) => Some(hi.as_str()),
+        Bound::Unbounded => None,
+    };
+    let base_len = hi.map_or(0, |hi| lo.bytes().zip(hi.bytes()).take_while(|&(a, b)| a == b).count());
+
+    // each cut ends one run and starts the next
+    let mut cuts = Vec::new();
+    if let Some(delimiter) = delimiter.filter(|d| !d.is_empty()) {
+        // the keys of a directory the start passes through end the start's runs
+        for len in directories(&lo, base_len, delimiter).into_iter().rev() {
+            if let Some(next) = prefix_end(&lo[..len]) {
+                cuts.push((Bound::Excluded(next.clone()), Bound::Included(next)));
+            }
+        }
+        // and a directory the end passes through starts a run right after its own key
+        for len in hi.map_or(Vec::new(), |hi| directories(hi, base_len, delimiter)) {
+            let dir = hi.unwrap()[..len].to_string();
+            cuts.push((Bound::Included(dir.clone()), Bound::Excluded(dir)));
+        }
+    }
+
+    let starts = Some(start).into_iter().chain(cuts.iter().map(|cut| cut.1.clone()));
+    let ends = cuts.iter().map(|cut| cut.0.clone()).chain(Some(end));
+    Ok(starts
+        .zip(ends)
+        .filter(|(start, end)| !is_empty(start, end))
+        .map(|(start, end)| {
+            let prefix = match start {
+                Bound::Included(ref lo) | Bound::Excluded(ref lo) => shared_prefix(lo, end.as_ref().map(String::as_str)),
+                Bound::Unbounded => "",
+            };
+            ListObjectsRequest::new(prefix.to_string(), start.clone(), end, max_keys, delimiter.map(str::to_string))
+        })
+        .collect())
+}
+
+/// Returns the lengths of the prefixes of `key` longer than `base_len` that end with the
+/// delimiter, leaving out `key` itself.
+fn directories(key: &str, base_len: usize, delimiter: &str) -> Vec<usize> {
+    (base_len + 1..key.len())
+        .filter(|&len| key.as_bytes()[..len].ends_with(delimiter.as_bytes()))
+        .collect()
+}
+
+/// Returns `true` if no key lies between `start` and `end`.
+fn is_empty(start: &Bound<String>, end: &Bound<String>) -> bool {
+    match (start, end) {
+        (Bound::Included(lo), Bound::Included(hi)) => lo > hi,
+        (Bound::Included(lo), Bound::Excluded(hi)) | (Bound::Excluded(lo), Bound::Included(hi)) => lo >= hi,
+        // nothing sorts between `x` and `x\0`
+        (Bound::Excluded(lo), Bound::Excluded(hi)) => hi <= lo || hi.strip_prefix(lo.as_str()) == Some("\0"),
+        _ => false,
+    }
+}
+
+/// Finds the longest prefix of `lo` shared by every key from `lo` up to `end`.
+fn shared_prefix<'a>(lo: &'a str, end: Bound<&str>) -> &'a str {
+    // the keys starting with `p` run up to, but not including, its successor
+    let holds = |p: &str| match (lex::prefix_successor(p.as_bytes()), end) {
+        (None, _) => true,
+        (Some(_), Bound::Unbounded) => false,
+        (Some(next), Bound::Included(hi)) => hi.as_bytes() < &next[..],
+        (Some(next), Bound::Excluded(hi)) => hi.as_bytes() <= &next[..],
+    };
+    let longest = lo
+        .char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .rev()
+        .find(|&len| holds(&lo[..len]))
+        .unwrap_or(0);
+    &lo[..longest]
+}
+
+/// Returns the smallest key above every key starting with `prefix`, or `None` if there is none.
+fn prefix_end(prefix: &str) -> Option<String> {
+    let mut out = prefix.to_string();
+    while let Some(last) = out.pop() {
+        if let Some(next) = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32) {
+            out.push(next);
+            return Some(out);
+        }
+    }
+    None
+}
+
+/// Finds a key a little less than `key`, by lowering its last character.
+///
+/// `StartAfter` is exclusive, so an inclusive start needs a key before it. The keys
+/// between the two are the ones starting with the lowered key; they are listed and then
+/// dropped by the client.
+fn predecessor(key: &str) -> Option<String> {
+    let last = key.chars().next_back()?;
+    let mut out = key[..key.len() - last.len_utf8()].to_string();
+    // nothing sorts between `x` and `x\0`
+    if last != '\0' {
+        out.push((0..last as u32).rev().find_map(char::from_u32).unwrap());
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(prefix: &str, start_after: Option<&str>, end: Bound<&str>) -> ListObjectsRequest {
+        ListObjectsRequest {
+            prefix: prefix.to_string(),
+            start_after: start_after.map(str::to_string),
+            max_keys: None,
+            delimiter: None,
+            start: Bound::Unbounded,
+            end: end.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn lists_a_range_with_one_request() {
+        let keys = ["a", "ab", "abc", "abd", "abd/x", "abe", "ac", "b", "\u{e9}", "\u{10ffff}"];
+        let ranges: Vec<(Bound<&str>, Bound<&str>)> = vec![
+            (Bound::Included("abc"), Bound::Included("abd/x")),
+            (Bound::Excluded("abc"), Bound::Excluded("ac")),
+            (Bound::Included("ab"), Bound::Excluded("ac")),
+            (Bound::Included("a"), Bound::Unbounded),
+            (Bound::Unbounded, Bound::Included("abd")),
+            (Bound::Included("abd"), Bound::Included("abd")),
+            (Bound::Included("b"), Bound::Included("\u{10ffff}")),
+            (Bound::Included("\u{e8}"), Bound::Excluded("\u{ea}")),
+        ];
+        for range in ranges {
+            let plan = plan_range::<&str, _>(range, None, None).unwrap();
+            assert_eq!(plan.len(), 1);
+            for key in &keys {
+                assert_eq!(plan[0].contains(key), range.contains(key), "{:?} in {:?}", key, range);
+            }
+        }
+    }
+    #[test]
+    fn picks_the_longest_shared_prefix() {
+        let plan = plan_range("user:".."user;", None, None).unwrap();
+        assert_eq!(plan, vec![request("user:", None, Bound::Unbounded)]);
+
+        let plan = plan_range("user:10"..="user:19", Some(100), Some("/")).unwrap();
+        assert_eq!(plan.len(), 1);
+        assert_eq!(plan[0].prefix, "user:1");
+        assert_eq!(plan[0].max_keys, Some(100));
+        assert_eq!(plan[0].delimiter, Some("/".to_string()));
+        assert_eq!(plan[0].end, Bound::Included("user:19".to_string()));
+
+        assert_eq!(plan_range::<&str, _>(.., None, None).unwrap(), vec![request("", None, Bound::Unbounded)]);
+    }
+    #[test]
+    fn starts_a_little_before_the_range() {
+        assert_eq!(predecessor("user:10"), Some("user:1/".to_string()));
+        assert_eq!(predecessor("a\0"), Some("a".to_string()));
+        assert_eq!(predecessor(""), None);
+        assert_eq!(predecessor("\u{e000}"), Some("\u{d7ff}".to_string()));
+
+        // keys between `StartAfter` and the start are listed, then dropped
+        let plan = plan_range("user:10".., None, None).unwrap();
+        assert_eq!(plan[0].start_after, Some("user:1/".to_string()));
+        assert!(!plan[0].contains("user:1/zzz"));
+        assert!(plan[0].contains("user:10"));
+    }
+    #[test]
+    fn rejects_inverted_and_skips_empty_ranges() {
+        assert_eq!(plan_range("b".."a", None, None), Err(RangePrefixError::InvertedRange));
+        assert_eq!(plan_range("a".."a", None, None), Ok(vec![]));
+        assert_eq!(plan_range::<&str, _>((Bound::Excluded("a"), Bound::Included("a")), None, None), Ok(vec![]));
+    }
+    #[test]
+    fn plans_a_request_per_directory() {
+        let mut keys = vec!["2017/".to_string(), "2017/05/".to_string(), "2017/05/02/".to_string()];
+        for month in 1..=6 {
+            for day in 1..=28 {
+                keys.push(format!("2017/{:02}/{:02}/00", month, day));
+            }
+        }
+        keys.sort();
+
+        let plan = plan_range("2017/03/15/"..="2017/05/02/", None, Some("/")).unwrap();
+        let prefixes: Vec<&str> = plan.iter().map(|request| request.prefix.as_str()).collect();
+        assert_eq!(prefixes, vec!["2017/03/", "2017/0", "2017/05/"]);
+        assert_eq!(plan[0].start_after, Some("2017/03/15.".to_string()));
+        assert_eq!(plan[2].end, Bound::Included("2017/05/02/".to_string()));
+
+        for range in &[("2017/03/15/", "2017/05/02/"), ("2017/", "2017/06/28/00"), ("2017/01/3", "2017/02/")] {
+            for delimiter in &[None, Some("/"), Some("/0")] {
+                let plan = plan_range(range.0..=range.1, None, *delimiter).unwrap();
+                for key in &keys {
+                    let hits = plan.iter().filter(|request| request.contains(key)).count();
+                    assert_eq!(hits, (range.0..=range.1).contains(&key.as_str()) as usize, "{:?} in {:?}", key, range);
+                }
+            }
+        }
+    }
+    #[test]
+    fn splits_ranges_into_disjoint_runs() {
+        let mut keys = vec![String::new()];
+        for len in 1..=3 {
+            let shorter: Vec<String> = keys.iter().filter(|key| key.chars().count() == len - 1).cloned().collect();
+            keys.extend(shorter.iter().flat_map(|key| ['/', 'a', '\u{e9}'].iter().map(move |&c| format!("{}{}", key, c))));
+        }
+        keys.sort();
+
+        for lo in &keys {
+            for hi in keys.iter().filter(|hi| lo <= *hi) {
+                for &range in &[(Bound::Included(lo.as_str()), Bound::Included(hi.as_str())), (Bound::Excluded(lo.as_str()), Bound::Excluded(hi.as_str()))] {
+                    let plan = plan_range::<&str, _>(range, None, Some("/")).unwrap();
+                    for key in &keys {
+                        let hits = plan.iter().filter(|request| request.contains(key)).count();
+                        assert_eq!(hits, range.contains(key.as_str()) as usize, "{:?} in {:?}: {:?}", key, range, plan);
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    fn descends_into_common_prefixes() {
+        let plan = plan_range("2017/03/15/"..="2017/05/02/", None, Some("/")).unwrap();
+        let (march, months, may) = (&plan[0], &plan[1], &plan[2]);
+
+        assert_eq!(march.descend("2017/03/14/"), None);
+        assert_eq!(march.descend("2017/03/15/").unwrap().start_after, None);
+
+        let april = months.descend("2017/04/").unwrap();
+        assert_eq!(april, ListObjectsRequest {
+            delimiter: Some("/".to_string()),
+            ..request("2017/04/", None, Bound::Unbounded)
+        });
+        assert_eq!(months.descend("2017/03/"), None);
+        assert_eq!(months.descend("2017/06/"), None);
+
+        assert_eq!(may.descend("2017/05/03/"), None);
+        let day = may.descend("2017/05/02/").unwrap();
+        assert_eq!(day.end, Bound::Included("2017/05/02/".to_string()));
+        assert!(day.contains("2017/05/02/") && !day.contains("2017/05/02/00"));
+    }
+}
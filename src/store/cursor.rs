@@ -0,0 +1,356 @@
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+
+use codec::{self, Reader, Writer};
+use error::{DecodeError, QueryError};
+use key_range;
+use lex;
+use store::{coarse_cover, Capabilities, PrefixStore, MAX_PREFIXES, PAGE_LIMIT};
+
+const MAGIC: &[u8] = b"BPRC";
+const VERSION: u8 = 1;
+
+/// The secret that signs [`RangeCursor`]s.
+///
+/// Cursors signed with one key are rejected by every other, so clients can hold cursors
+/// without being able to forge or alter them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CursorKey([u64; 2]);
+
+impl CursorKey {
+    /// Creates a key from 16 secret bytes.
+    pub fn new(secret: [u8; 16]) -> CursorKey {
+        let mut halves = [[0; 8]; 2];
+        halves[0].copy_from_slice(&secret[..8]);
+        halves[1].copy_from_slice(&secret[8..]);
+        CursorKey([u64::from_le_bytes(halves[0]), u64::from_le_bytes(halves[1])])
+    }
+}
+
+impl fmt::Debug for CursorKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CursorKey(..)")
+    }
+}
+
+/// Where a paginated range query left off.
+///
+/// A cursor records which prefix of the range's cover is being listed, the store's own
+/// cursor within it and the last key returned, so the next page resumes there without
+/// listing the finished prefixes again. It only resumes the range it was issued for.
+///
+/// Cursors are handed to clients with [`encode`](#method.encode), which signs them, and
+/// taken back with [`decode`](#method.decode), which rejects any that were altered.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RangeCursor {
+    /// Identifies the range and how it is listed.
+    range: u64,
+    part: usize,
+    token: Option<Vec<u8>>,
+    last_key: Option<Vec<u8>>,
+}
+
+impl RangeCursor {
+    /// Returns the index of the prefix, or range for stores with native range scans,
+    /// being listed.
+    pub fn part(&self) -> usize {
+        self.part
+    }
+
+    /// Returns the store's cursor within the part, or `None` to start it from the beginning.
+    pub fn token(&self) -> Option<&[u8]> {
+        self.token.as_deref()
+    }
+
+    /// Returns the last key returned before the cursor.
+    pub fn last_key(&self) -> Option<&[u8]> {
+        self.last_key.as_deref()
+    }
+
+    /// Serializes and signs the cursor.
+    pub fn to_bytes(&self, key: &CursorKey) -> Vec<u8> {
+        let mut writer = Writer::new(MAGIC, VERSION);
+        writer.u64(self.range);
+        writer.u64(self.part as u64);
+        for field in &[&self.token, &self.last_key] {
+            match **field {
+                Some(ref bytes) => {
+                    writer.u64(1);
+                    writer.bytes(bytes);
+                }
+                None => writer.u64(0),
+            }
+        }
+        writer.sign(key.0)
+    }
+
+    /// Reads back a cursor written by [`to_bytes`](#method.to_bytes) with the same key.
+    pub fn from_bytes(bytes: &[u8], key: &CursorKey) -> Result<RangeCursor, DecodeError> {
+        let (mut reader, version) = Reader::new(bytes, MAGIC)?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        reader.verify(bytes, key.0)?;
+
+        let range = reader.u64()?;
+        let part = reader.usize()?;
+        let mut optional = || match reader.u64()? {
+            0 => Ok(None),
+            1 => Ok(Some(reader.bytes()?.to_vec())),
+            _ => Err(DecodeError::Corrupt),
+        };
+        let token = optional()?;
+        let last_key = optional()?;
+        reader.finish()?;
+        Ok(RangeCursor {
+            range,
+            part,
+            token,
+            last_key,
+        })
+    }
+
+    /// Serializes and signs the cursor as text, for use in URLs and JSON.
+    pub fn encode(&self, key: &CursorKey) -> String {
+        codec::to_hex(&self.to_bytes(key))
+    }
+
+    /// Reads back a cursor written by [`encode`](#method.encode) with the same key.
+    pub fn decode(text: &str, key: &CursorKey) -> Result<RangeCursor, DecodeError> {
+        RangeCursor::from_bytes(&codec::from_hex(text).ok_or(DecodeError::UnknownFormat)?, key)
+    }
+}
+
+/// One page of a paginated range query.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangePage {
+    /// The keys listed.
+    pub keys: Vec<Vec<u8>>,
+    /// The cursor for the next page, or `None` if this is the last page.
+    pub cursor: Option<RangeCursor>,
+}
+
+/// Lists one page of the keys of a store within `range`.
+///
+/// Keys are listed as by [`range_query`](fn.range_query.html), beginning at `cursor`,
+/// until `limit` keys have been found or the range is exhausted. Keys are in ascending
+/// order across pages for [ordered](struct.Capabilities.html#structfield.ordered) stores.
+/// Other stores are sorted within a page, and may repeat a key on a later page if the
+/// store does.
+///
+/// A cursor issued for a different range, or a store that scans ranges differently, is
+/// rejected with [`QueryError::CursorMismatch`].
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::store::{range_page, CursorKey, MemoryPrefixStore, RangeCursor};
+///
+/// let store: MemoryPrefixStore = (5..25).map(|i| (format!("user:{:02}", i), "")).collect();
+/// let key = CursorKey::new(*b"sixteen byte key");
+///
+/// let first = range_page(&store, "user:10".."user:20", None, 4).unwrap();
+/// let token = first.cursor.unwrap().encode(&key);
+///
+/// // later, from the client's token
+/// let cursor = RangeCursor::decode(&token, &key).unwrap();
+/// let second = range_page(&store, "user:10".."user:20", Some(&cursor), 4).unwrap();
+/// String::from_utf8_lossy(&second.keys[0]);
+/// // "user:14"
+/// ```
+pub fn range_page<S, K, R>(store: &S, range: R, cursor: Option<&RangeCursor>, limit: usize) -> Result<RangePage, QueryError<S::Error>>
+where
+    S: PrefixStore + ?Sized,
+    K: AsRef<[u8]>,
+    R: RangeBounds<K>,
+{
+    let start = range.start_bound().map(K::as_ref);
+    let end = range.end_bound().map(K::as_ref);
+    let cover = lex::bounds_cover(start, end).map_err(QueryError::Range)?;
+    let capabilities = store.capabilities();
+    let native = capabilities.native_range;
+    let ranges = if native { key_range::lex_ranges(&cover) } else { Vec::new() };
+    let prefixes = if native {
+        Vec::new()
+    } else {
+        coarse_cover(&cover, if capabilities.ordered { MAX_PREFIXES } else { 1 })
+    };
+    let parts = if native { ranges.len() } else { prefixes.len() };
+
+    let fingerprint = fingerprint(start, end, capabilities);
+    let (mut part, mut token, mut last_key) = match cursor {
+        Some(cursor) if cursor.range != fingerprint || cursor.part >= parts => return Err(QueryError::CursorMismatch),
+        Some(cursor) => (cursor.part, cursor.token.clone(), cursor.last_key.clone()),
+        None => (0, None, None),
+    };
+
+    let limit = limit.max(1);
+    let mut keys = Vec::new();
+    while part < parts && keys.len() < limit {
+        let page_limit = (limit - keys.len()).min(PAGE_LIMIT);
+        let page = if native {
+            store.list_range(&ranges[part], token.as_deref(), page_limit)
+        } else {
+            store.list_prefix(prefixes[part].bytes(), token.as_deref(), page_limit)
+        }
+        .map_err(QueryError::Store)?;
+
+        let mut listed: Vec<Vec<u8>> = page
+            .keys
+            .into_iter()
+            .filter(|key| native || (start, end).contains(&key[..]))
+            // a store's cursor may land before keys already returned
+            .filter(|key| !capabilities.ordered || last_key.as_ref().map_or(true, |last| key > last))
+            .collect();
+        if !capabilities.ordered {
+            listed.sort_unstable();
+            listed.dedup();
+        }
+        if let Some(last) = listed.last() {
+            last_key = Some(last.clone());
+        }
+        keys.extend(listed);
+
+        token = page.cursor;
+        if token.is_none() {
+            part += 1;
+        }
+    }
+
+    Ok(RangePage {
+        keys,
+        cursor: if part < parts {
+            Some(RangeCursor {
+                range: fingerprint,
+                part,
+                token,
+                last_key,
+            })
+        } else {
+            None
+        },
+    })
+}
+
+/// Identifies a range and the capabilities that decide how it is split into parts.
+fn fingerprint(start: Bound<&[u8]>, end: Bound<&[u8]>, capabilities: Capabilities) -> u64 {
+    let mut writer = Writer::new(MAGIC, VERSION);
+    for bound in &[start, end] {
+        match *bound {
+            Bound::Included(key) => {
+                writer.u64(0);
+                writer.bytes(key);
+            }
+            Bound::Excluded(key) => {
+                writer.u64(1);
+                writer.bytes(key);
+            }
+            Bound::Unbounded => writer.u64(2),
+        }
+    }
+    writer.u64(capabilities.native_range as u64);
+    writer.u64(capabilities.ordered as u64);
+    codec::siphash([0, 0], &writer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    use store::{range_query, Capabilities, KeyPage, MemoryPrefixStore};
+
+    const KEY: CursorKey = CursorKey([1, 2]);
+
+    /// Lists a memory store by prefix only, recording the prefixes listed.
+    struct Prefixes(MemoryPrefixStore, RefCell<Vec<Vec<u8>>>);
+
+    impl PrefixStore for Prefixes {
+        type Error = ();
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                ordered: true,
+                ..Capabilities::default()
+            }
+        }
+
+        fn list_prefix(&self, prefix: &[u8], cursor: Option<&[u8]>, limit: usize) -> Result<KeyPage, ()> {
+            self.1.borrow_mut().push(prefix.to_vec());
+            Ok(self.0.list_prefix(prefix, cursor, limit).unwrap())
+        }
+    }
+
+    fn store() -> MemoryPrefixStore {
+        (0..200).map(|i| (format!("k:{:03}", i), "")).collect()
+    }
+
+    /// Lists every page, passing the cursor through its text form.
+    fn pages<S: PrefixStore>(store: &S, limit: usize) -> Vec<RangePage>
+    where
+        S::Error: fmt::Debug,
+    {
+        let mut pages = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = range_page(store, "k:015".."k:142", cursor.as_ref(), limit).unwrap();
+            cursor = page.cursor.as_ref().map(|cursor| RangeCursor::decode(&cursor.encode(&KEY), &KEY).unwrap());
+            pages.push(page);
+            if cursor.is_none() {
+                return pages;
+            }
+        }
+    }
+
+    #[test]
+    fn resumes_where_the_last_page_ended() {
+        let memory = store();
+        let prefixes = Prefixes(store(), RefCell::default());
+        let expected = range_query(&memory, "k:015".."k:142").unwrap();
+
+        for &limit in &[1, 7, 50, 1000] {
+            for pages in [pages(&memory, limit), pages(&prefixes, limit)] {
+                assert!(pages.iter().all(|page| page.keys.len() <= limit));
+                assert!(pages[..pages.len() - 1].iter().all(|page| page.keys.len() == limit));
+                assert_eq!(pages.into_iter().flat_map(|page| page.keys).collect::<Vec<_>>(), expected);
+            }
+
+            // finished prefixes are never listed again
+            let mut listed = prefixes.1.borrow_mut();
+            assert!(listed.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", listed);
+            listed.dedup();
+            assert_eq!(*listed, vec![b"k:0".to_vec(), b"k:1".to_vec()]);
+            listed.clear();
+        }
+    }
+    #[test]
+    fn rejects_altered_cursors() {
+        let cursor = range_page(&store(), "k:015".."k:142", None, 10).unwrap().cursor.unwrap();
+        let bytes = cursor.to_bytes(&KEY);
+        assert_eq!(RangeCursor::from_bytes(&bytes, &KEY), Ok(cursor.clone()));
+
+        for i in 0..bytes.len() {
+            let mut altered = bytes.clone();
+            altered[i] ^= 1;
+            assert!(RangeCursor::from_bytes(&altered, &KEY).is_err());
+        }
+        assert_eq!(RangeCursor::from_bytes(&bytes, &CursorKey([1, 3])), Err(DecodeError::InvalidSignature));
+        assert_eq!(RangeCursor::from_bytes(&bytes[..bytes.len() - 1], &KEY), Err(DecodeError::InvalidSignature));
+        assert_eq!(RangeCursor::from_bytes(&bytes[..MAGIC.len() + 3], &KEY), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(RangeCursor::decode("not hex", &KEY), Err(DecodeError::UnknownFormat));
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()] = VERSION + 1;
+        assert_eq!(RangeCursor::from_bytes(&newer, &KEY), Err(DecodeError::UnsupportedVersion(VERSION + 1)));
+    }
+    #[test]
+    fn rejects_cursors_of_other_ranges() {
+        let memory = store();
+        let prefixes = Prefixes(store(), RefCell::default());
+        let cursor = range_page(&memory, "k:015".."k:142", None, 10).unwrap().cursor.unwrap();
+
+        assert_eq!(range_page(&memory, "k:015"..="k:142", Some(&cursor), 10), Err(QueryError::CursorMismatch));
+        assert_eq!(range_page(&prefixes, "k:015".."k:142", Some(&cursor), 10), Err(QueryError::CursorMismatch));
+        assert!(range_page(&memory, "k:015".."k:142", Some(&cursor), 10).is_ok());
+    }
+}
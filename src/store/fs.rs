@@ -0,0 +1,313 @@
+//! A store over the files of a local directory tree, keyed like S3.
+//!
+//! Keys map to paths below the store's root, and only keys that stay below it are
+//! accepted: `..`, `.`, empty components and absolute paths are rejected.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use lex::LexPrefix;
+use store::{Capabilities, KeyPage, PrefixStore};
+
+/// A read-only store whose keys are the files under a directory.
+///
+/// A key is a file's path relative to the root, with components joined by `/`, so
+/// `data/2017/08/30/part-0` is the file `part-0` in the directory `data/2017/08/30`.
+/// Empty directories hold no keys, and files whose names are not UTF-8 are skipped.
+/// Symbolic links are listed as keys and never followed.
+///
+/// # Example
+///
+/// ```no_run
+/// use binary_prefix::cover;
+/// use binary_prefix::store::FsPrefixStore;
+///
+/// let store = FsPrefixStore::new("/srv/batch");
+///
+/// // only reads the directories that can hold keys in the range
+/// let keys = store.list_cover(&cover("data/2017/08/30".."data/2017/09/02").unwrap()).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsPrefixStore {
+    root: PathBuf,
+}
+
+impl FsPrefixStore {
+    /// Creates a store over the files under `root`.
+    pub fn new<P: Into<PathBuf>>(root: P) -> FsPrefixStore {
+        FsPrefixStore { root: root.into() }
+    }
+
+    /// Returns the root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the path of the file holding `key`.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the key could name a file outside the
+    /// root: it is empty, starts or ends with `/`, or has an empty, `.` or `..` component, or
+    /// one containing a backslash or NUL.
+    pub fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let mut path = self.root.clone();
+        for component in key.split('/') {
+            if component.is_empty() || component == "." || component == ".." || component.contains(['\\', '\0']) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("key {:?} is not a relative path below the root", key)));
+            }
+            path.push(component);
+        }
+        Ok(path)
+    }
+
+    /// Lists the keys starting with `prefix`, in ascending order.
+    pub fn list_all(&self, prefix: &str) -> io::Result<Vec<String>> {
+        self.list_cover(&[LexPrefix::Prefix(prefix.as_bytes().to_vec())])
+    }
+
+    /// Lists the keys matched by a lexicographic cover, in ascending order.
+    ///
+    /// `cover` must be sorted and free of overlaps, as returned by the `range_cover`
+    /// functions. The walk only enters directories that can hold a matched key.
+    pub fn list_cover(&self, cover: &[LexPrefix]) -> io::Result<Vec<String>> {
+        self.walk(cover, None, usize::MAX)
+    }
+
+    /// Lists up to `limit` keys matched by the cover that are above `after`.
+    fn walk(&self, cover: &[LexPrefix], after: Option<&[u8]>, limit: usize) -> io::Result<Vec<String>> {
+        let mut walk = Walk {
+            cover,
+            after,
+            limit,
+            keys: Vec::new(),
+        };
+        if self.root.is_dir() {
+            walk.dir(&self.root, String::new())?;
+        }
+        Ok(walk.keys)
+    }
+}
+
+impl PrefixStore for FsPrefixStore {
+    type Error = io::Error;
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            ordered: true,
+            delimiter: false,
+            native_range: false,
+        }
+    }
+
+    /// Lists keys in ascending order. The cursor is the last key listed.
+    fn list_prefix(&self, prefix: &[u8], cursor: Option<&[u8]>, limit: usize) -> io::Result<KeyPage> {
+        let limit = limit.max(1);
+        let mut keys: Vec<Vec<u8>> = self
+            .walk(&[LexPrefix::Prefix(prefix.to_vec())], cursor, limit + 1)?
+            .into_iter()
+            .map(String::into_bytes)
+            .collect();
+        let more = keys.len() > limit;
+        keys.truncate(limit);
+        Ok(KeyPage {
+            cursor: keys.last().cloned().filter(|_| more),
+            keys,
+        })
+    }
+}
+
+/// A depth-first walk that lists keys in ascending order.
+struct Walk<'a> {
+    cover: &'a [LexPrefix],
+    /// Keys up to this one are skipped.
+    after: Option<&'a [u8]>,
+    limit: usize,
+    keys: Vec<String>,
+}
+
+impl<'a> Walk<'a> {
+    /// Lists the matched keys under `dir`, whose keys all start with `head`.
+    fn dir(&mut self, dir: &Path, head: String) -> io::Result<()> {
+        let mut children = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            let is_dir = entry.file_type()?.is_dir();
+            // a directory stands for the keys under `name/`, which is how it must sort
+            let mut key = head.clone() + &name;
+            if is_dir {
+                key.push('/');
+            }
+            children.push((key, is_dir, entry.path()));
+        }
+        children.sort_unstable();
+
+        for (key, is_dir, path) in children {
+            if self.keys.len() >= self.limit {
+                break;
+            }
+            let bytes = key.as_bytes();
+            if is_dir {
+                // every key under the directory is below `after` if they part within `key`
+                let passed = self.after.is_some_and(|after| bytes < after && !after.starts_with(bytes));
+                if !passed && reaches(self.cover, bytes) {
+                    self.dir(&path, key)?;
+                }
+            } else if self.after.map_or(true, |after| bytes > after) && matches(self.cover, bytes) {
+                self.keys.push(key);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if some key starting with `prefix` is matched by the cover.
+fn reaches(cover: &[LexPrefix], prefix: &[u8]) -> bool {
+    let i = cover.partition_point(|entry| entry.bytes() < prefix);
+    // an entry under the prefix, or a prefix entry above it
+    cover.get(i).is_some_and(|entry| entry.bytes().starts_with(prefix))
+        || i.checked_sub(1).is_some_and(|i| match cover[i] {
+            LexPrefix::Prefix(ref bytes) => prefix.starts_with(bytes),
+            LexPrefix::Key(_) => false,
+        })
+}
+
+/// Returns `true` if `key` is matched by the cover.
+fn matches(cover: &[LexPrefix], key: &[u8]) -> bool {
+    // entries don't overlap, so only the last one not above the key can match it
+    let i = cover.partition_point(|entry| entry.bytes() <= key);
+    i.checked_sub(1).is_some_and(|i| cover[i].matches(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::ops::{Bound, RangeBounds};
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use bounds::cover;
+    use store::range_query;
+
+    /// A directory tree that is removed when dropped.
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(files: &[&str]) -> Tree {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let root = env::temp_dir().join(format!("binary_prefix-{}-{}", process::id(), NEXT.fetch_add(1, Ordering::SeqCst)));
+            for file in files {
+                let path = FsPrefixStore::new(&root).path(file).unwrap();
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, file).unwrap();
+            }
+            fs::create_dir_all(root.join("empty")).unwrap();
+            Tree(root)
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const FILES: &[&str] = &[
+        "a-b",
+        "a/x",
+        "a/y/z",
+        "data/2017/08/29/part-0",
+        "data/2017/08/30/part-0",
+        "data/2017/08/30/part-1",
+        "data/2017/08/31/part-0",
+        "data/2017/09/01/part-0",
+        "data/2017/09/02/part-0",
+        "data/2017/10/01/part-0",
+        "data/2018/01/01/part-0",
+        "readme",
+    ];
+
+    /// Lists every key starting with `prefix`, `limit` at a time.
+    fn list(store: &FsPrefixStore, prefix: &str, limit: usize) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = store.list_prefix(prefix.as_bytes(), cursor.as_deref(), limit).unwrap();
+            assert!(page.keys.len() <= limit.max(1));
+            keys.extend(page.keys.into_iter().map(|key| String::from_utf8(key).unwrap()));
+            cursor = match page.cursor {
+                Some(next) => Some(next),
+                None => return keys,
+            };
+        }
+    }
+
+    #[test]
+    fn lists_keys_in_order() {
+        let tree = Tree::new(FILES);
+        let store = FsPrefixStore::new(&tree.0);
+
+        assert_eq!(store.list_all("").unwrap(), FILES);
+        assert_eq!(store.list_all("a").unwrap(), vec!["a-b", "a/x", "a/y/z"]);
+        assert_eq!(store.list_all("data/2017/08/3").unwrap(), vec![
+            "data/2017/08/30/part-0",
+            "data/2017/08/30/part-1",
+            "data/2017/08/31/part-0",
+        ]);
+        assert_eq!(store.list_all("empty").unwrap(), Vec::<String>::new());
+        assert_eq!(FsPrefixStore::new(tree.0.join("missing")).list_all("").unwrap(), Vec::<String>::new());
+
+        for &limit in &[0, 1, 2, 100] {
+            assert_eq!(list(&store, "", limit), FILES);
+            assert_eq!(list(&store, "a", limit), vec!["a-b", "a/x", "a/y/z"]);
+            assert_eq!(list(&store, "data/2017/08/3", limit), vec![
+                "data/2017/08/30/part-0",
+                "data/2017/08/30/part-1",
+                "data/2017/08/31/part-0",
+            ]);
+        }
+        assert_eq!(list(&store, "empty", 10), Vec::<String>::new());
+        assert_eq!(list(&FsPrefixStore::new(tree.0.join("missing")), "", 10), Vec::<String>::new());
+    }
+    #[test]
+    fn lists_ranges_exactly() {
+        let tree = Tree::new(FILES);
+        let store = FsPrefixStore::new(&tree.0);
+
+        for lo in FILES {
+            for hi in FILES.iter().filter(|hi| lo <= *hi) {
+                for &range in &[(Bound::Included(*lo), Bound::Included(*hi)), (Bound::Excluded(*lo), Bound::Excluded(*hi))] {
+                    let expected: Vec<&str> = FILES.iter().cloned().filter(|k| range.contains(k)).collect();
+                    let cover = cover::<&str, _>(range).unwrap();
+                    assert_eq!(store.list_cover(&cover).unwrap(), expected, "{:?}", range);
+                    assert_eq!(range_query::<_, &str, _>(&store, range).unwrap(), expected.iter().map(|k| k.as_bytes()).collect::<Vec<_>>());
+                }
+            }
+        }
+    }
+    #[test]
+    fn rejects_keys_outside_the_root() {
+        let store = FsPrefixStore::new("/srv/batch");
+
+        assert_eq!(store.path("data/2017/part-0").unwrap(), Path::new("/srv/batch/data/2017/part-0"));
+        for key in &["", "../../etc/passwd", "data/../../x", "/etc/passwd", "data//x", "data/", "./x", "a\\..\\x", "a\0"] {
+            assert_eq!(store.path(key).unwrap_err().kind(), io::ErrorKind::InvalidInput, "{:?}", key);
+        }
+    }
+    #[test]
+    fn prunes_subtrees_outside_the_cover() {
+        let cover = cover("data/2017/08/30".."data/2017/09/02").unwrap();
+
+        assert!(reaches(&cover, b"data/"));
+        assert!(reaches(&cover, b"data/2017/08/"));
+        assert!(reaches(&cover, b"data/2017/08/31/"));
+        assert!(!reaches(&cover, b"data/2017/08/29/"));
+        assert!(!reaches(&cover, b"data/2017/10/"));
+        assert!(!reaches(&cover, b"data/2018/"));
+        assert!(!reaches(&cover, b"readme/"));
+    }
+}
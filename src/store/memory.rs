@@ -0,0 +1,538 @@
+//! An in-memory store with the listing semantics of S3 and the `SCAN` semantics of Redis.
+//!
+//! Continuation tokens carry the key the next page starts from and a hash of the request
+//! they were issued for, so a token passed along with a different request is rejected.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::Bound;
+
+use codec;
+use error::{DecodeError, StoreError};
+use glob;
+use key_range::KeyRange;
+use lex;
+use s3::ListObjectsRequest;
+use store::{Capabilities, KeyPage, PrefixStore};
+
+/// The most keys S3 returns in one page.
+const MAX_KEYS: usize = 1000;
+/// The smallest hash table Redis allocates.
+const MIN_TABLE_SIZE: usize = 4;
+/// Identifies continuation tokens.
+const TOKEN_MAGIC: &[u8] = b"BPLT";
+/// The version of the continuation token format.
+const TOKEN_VERSION: u8 = 1;
+
+/// An in-memory key-value store that lists keys like S3 and scans them like Redis.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::s3::plan_range;
+/// use binary_prefix::store::MemoryPrefixStore;
+///
+/// let mut store = MemoryPrefixStore::new();
+/// for day in 10..20 {
+///     store.insert(format!("logs/2017-03-{}", day), "...");
+/// }
+///
+/// let plan = plan_range("logs/2017-03-12"..="logs/2017-03-14", Some(2), None).unwrap();
+/// let page = store.list_objects_v2(&plan[0], None).unwrap();
+///
+/// page.contents;
+/// // ["logs/2017-03-12", "logs/2017-03-13"]
+/// page.next_continuation_token.is_some();
+/// // true
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MemoryPrefixStore {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Keys by their hash with its bits reversed, which makes each `SCAN` bucket a range.
+    buckets: BTreeMap<u64, Vec<Vec<u8>>>,
+}
+
+/// One page of a `ListObjectsV2` response.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ListObjectsPage {
+    /// The keys listed, in ascending order.
+    pub contents: Vec<String>,
+    /// The prefixes that keys were rolled up into by the delimiter, in ascending order.
+    pub common_prefixes: Vec<String>,
+    /// The token for the next page, or `None` if this is the last page.
+    pub next_continuation_token: Option<String>,
+}
+
+impl ListObjectsPage {
+    /// Returns `true` if there are more pages.
+    pub fn is_truncated(&self) -> bool {
+        self.next_continuation_token.is_some()
+    }
+}
+
+impl MemoryPrefixStore {
+    /// Creates an empty store.
+    pub fn new() -> MemoryPrefixStore {
+        MemoryPrefixStore::default()
+    }
+
+    /// Returns the number of keys.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no keys.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Stores a value, returning the one it replaces.
+    pub fn insert<K: Into<Vec<u8>>, V: Into<Vec<u8>>>(&mut self, key: K, value: V) -> Option<Vec<u8>> {
+        let key = key.into();
+        let old = self.entries.insert(key.clone(), value.into());
+        if old.is_none() {
+            self.buckets.entry(slot(&key)).or_default().push(key);
+        }
+        old
+    }
+
+    /// Returns the value stored under `key`.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(key).map(|value| &value[..])
+    }
+
+    /// Removes a key, returning its value.
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let old = self.entries.remove(key)?;
+        let slot = slot(key);
+        let bucket = self.buckets.get_mut(&slot).unwrap();
+        bucket.retain(|k| k != key);
+        if bucket.is_empty() {
+            self.buckets.remove(&slot);
+        }
+        Some(old)
+    }
+
+    /// Returns the keys in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.entries.keys().map(|key| &key[..])
+    }
+
+    /// Lists one page of keys the way S3's `ListObjectsV2` does.
+    ///
+    /// Keys starting with `request.prefix` are listed in ascending order, beginning after
+    /// `request.start_after` on the first page and where the previous page ended on later
+    /// ones. With a delimiter, keys containing it after the prefix are rolled up into one
+    /// common prefix each. A page holds at most `request.max_keys` keys and common prefixes
+    /// together, and never more than 1000. `request.start` and `request.end` are for the
+    /// client and are ignored.
+    ///
+    /// Keys that are not UTF-8 cannot exist in S3 and are skipped. A continuation token
+    /// issued for a request with another prefix or `StartAfter` is rejected with
+    /// [`StoreError::ContinuationTokenMismatch`].
+    pub fn list_objects_v2(&self, request: &ListObjectsRequest, continuation_token: Option<&str>) -> Result<ListObjectsPage, StoreError> {
+        let prefix = request.prefix.as_bytes();
+        let delimiter = request.delimiter.as_ref().map(|d| d.as_bytes()).filter(|d| !d.is_empty());
+        let max_keys = request.max_keys.map_or(MAX_KEYS, |n| (n as usize).min(MAX_KEYS));
+
+        let mut from = match (continuation_token, &request.start_after) {
+            (Some(token), _) => Bound::Included(decode_token(request, token)?),
+            (None, Some(after)) if after.as_bytes() >= prefix => Bound::Excluded(after.as_bytes().to_vec()),
+            (None, _) => Bound::Included(prefix.to_vec()),
+        };
+
+        let mut page = ListObjectsPage::default();
+        let mut listed = 0;
+        while let Some(key) = self.next_key(&from) {
+            if !key.starts_with(prefix) {
+                break;
+            }
+            if listed == max_keys {
+                // a page that can hold nothing has no next page either
+                if max_keys > 0 {
+                    page.next_continuation_token = Some(encode_token(request, key));
+                }
+                break;
+            }
+            let key = match String::from_utf8(key.to_vec()) {
+                Ok(key) => key,
+                Err(err) => {
+                    from = Bound::Excluded(err.into_bytes());
+                    continue;
+                }
+            };
+
+            let rest = &key.as_bytes()[prefix.len()..];
+            let rolled_up = delimiter.and_then(|d| rest.windows(d.len()).position(|w| w == d).map(|i| i + d.len()));
+            listed += 1;
+            match rolled_up {
+                Some(len) => {
+                    let common = key[..prefix.len() + len].to_string();
+                    // resume after every key under the common prefix
+                    from = match lex::prefix_successor(common.as_bytes()) {
+                        Some(next) => Bound::Included(next),
+                        None => Bound::Unbounded,
+                    };
+                    page.common_prefixes.push(common);
+                    if from == Bound::Unbounded {
+                        break;
+                    }
+                }
+                None => {
+                    from = Bound::Excluded(key.clone().into_bytes());
+                    page.contents.push(key);
+                }
+            }
+        }
+        Ok(page)
+    }
+
+    /// Scans a batch of keys the way Redis' `SCAN` does.
+    ///
+    /// Start with cursor `0` and pass the returned cursor to the next call; the scan is
+    /// complete when the returned cursor is `0` again. Keys are visited a hash bucket at a
+    /// time, in no useful order, until about `count` keys have been examined. Only those
+    /// matching `pattern` are returned, so a batch may be empty before the scan is done.
+    ///
+    /// Every key present for the whole scan is returned at least once. As in Redis, keys
+    /// may be returned more than once if the store shrinks during the scan.
+    pub fn scan(&self, cursor: u64, pattern: Option<&[u8]>, count: usize) -> (u64, Vec<Vec<u8>>) {
+        let size = self.entries.len().next_power_of_two().max(MIN_TABLE_SIZE);
+        let mask = size as u64 - 1;
+        let tail = u64::MAX >> size.trailing_zeros();
+
+        let mut cursor = cursor;
+        let mut keys = Vec::new();
+        let mut examined = 0;
+        loop {
+            let first = (cursor & mask).reverse_bits();
+            for key in self.buckets.range(first..=first | tail).flat_map(|(_, bucket)| bucket) {
+                examined += 1;
+                if pattern.map_or(true, |pattern| glob::matches(pattern, key)) {
+                    keys.push(key.clone());
+                }
+            }
+
+            // Redis' reverse binary increment, which visits every bucket of a larger or
+            // smaller table whatever the size when the scan started
+            cursor = (cursor | !mask).reverse_bits().wrapping_add(1).reverse_bits();
+            if cursor == 0 || examined >= count {
+                return (cursor, keys);
+            }
+        }
+    }
+
+    fn next_key(&self, from: &Bound<Vec<u8>>) -> Option<&[u8]> {
+        let start = match *from {
+            Bound::Included(ref key) => Bound::Included(&key[..]),
+            Bound::Excluded(ref key) => Bound::Excluded(&key[..]),
+            Bound::Unbounded => return None,
+        };
+        self.entries
+            .range::<[u8], _>((start, Bound::Unbounded))
+            .next()
+            .map(|(key, _)| &key[..])
+    }
+
+    /// Lists up to `limit` keys from `start` that are below `end` and satisfy `keep`.
+    fn page<F: Fn(&[u8]) -> bool>(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, limit: usize, keep: F) -> KeyPage {
+        let empty = match (start, end) {
+            (Bound::Included(a) | Bound::Excluded(a), Bound::Excluded(b)) => a >= b,
+            _ => false,
+        };
+        if empty {
+            return KeyPage::default();
+        }
+
+        let mut listed = self
+            .entries
+            .range::<[u8], _>((start, end))
+            .map(|(key, _)| key)
+            .take_while(|key| keep(key));
+        let keys: Vec<Vec<u8>> = listed.by_ref().take(limit.max(1)).cloned().collect();
+        KeyPage {
+            cursor: listed.next().and(keys.last().cloned()),
+            keys,
+        }
+    }
+}
+
+impl PrefixStore for MemoryPrefixStore {
+    type Error = Infallible;
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            ordered: true,
+            delimiter: true,
+            native_range: true,
+        }
+    }
+
+    /// Lists keys in ascending order. The cursor is the last key listed.
+    fn list_prefix(&self, prefix: &[u8], cursor: Option<&[u8]>, limit: usize) -> Result<KeyPage, Infallible> {
+        let start = cursor.map_or(Bound::Included(prefix), Bound::Excluded);
+        Ok(self.page(start, Bound::Unbounded, limit, |key| key.starts_with(prefix)))
+    }
+
+    /// Lists keys in ascending order. The cursor is the last key listed.
+    fn list_range(&self, range: &KeyRange<Vec<u8>>, cursor: Option<&[u8]>, limit: usize) -> Result<KeyPage, Infallible> {
+        let start = cursor.map_or(Bound::Included(&range.start[..]), Bound::Excluded);
+        let end = range.end.as_ref().map_or(Bound::Unbounded, |end| Bound::Excluded(&end[..]));
+        Ok(self.page(start, end, limit, |_| true))
+    }
+}
+
+impl<K: Into<Vec<u8>>, V: Into<Vec<u8>>> FromIterator<(K, V)> for MemoryPrefixStore {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> MemoryPrefixStore {
+        let mut store = MemoryPrefixStore::new();
+        store.extend(iter);
+        store
+    }
+}
+
+impl<K: Into<Vec<u8>>, V: Into<Vec<u8>>> Extend<(K, V)> for MemoryPrefixStore {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// Returns where a key sits in the reversed-hash index.
+fn slot(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish().reverse_bits()
+}
+
+/// Encodes a continuation token: a hash of the request and the key the next page starts from.
+fn encode_token(request: &ListObjectsRequest, from: &[u8]) -> String {
+    let mut writer = codec::Writer::new(TOKEN_MAGIC, TOKEN_VERSION);
+    writer.u64(request_hash(request));
+    writer.bytes(from);
+    codec::to_hex(&writer.finish())
+}
+
+/// Decodes a continuation token issued for `request`, returning the key the next page starts from.
+fn decode_token(request: &ListObjectsRequest, token: &str) -> Result<Vec<u8>, StoreError> {
+    let bytes = codec::from_hex(token).ok_or(StoreError::InvalidContinuationToken)?;
+    let read = || {
+        let (mut reader, version) = codec::Reader::new(&bytes, TOKEN_MAGIC)?;
+        if version != TOKEN_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let hash = reader.u64()?;
+        let from = reader.bytes()?.to_vec();
+        reader.finish()?;
+        Ok((hash, from))
+    };
+    let (hash, from) = read().map_err(|_: DecodeError| StoreError::InvalidContinuationToken)?;
+    if hash != request_hash(request) {
+        return Err(StoreError::ContinuationTokenMismatch);
+    }
+    Ok(from)
+}
+
+/// Hashes the parameters a continuation token is bound to: the prefix and `StartAfter`.
+fn request_hash(request: &ListObjectsRequest) -> u64 {
+    let mut writer = codec::Writer::new(TOKEN_MAGIC, TOKEN_VERSION);
+    writer.bytes(request.prefix.as_bytes());
+    match request.start_after {
+        Some(ref after) => {
+            writer.u64(1);
+            writer.bytes(after.as_bytes());
+        }
+        None => writer.u64(0),
+    }
+    codec::siphash([0, 0], &writer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    use glob::scan_patterns;
+    use lex::range_cover;
+    use merge::merge_unordered;
+    use s3::plan_range;
+
+    fn days() -> MemoryPrefixStore {
+        let mut store = MemoryPrefixStore::new();
+        for month in 1..=6 {
+            for day in 1..=28 {
+                for hour in &["00", "12"] {
+                    store.insert(format!("2017/{:02}/{:02}/{}", month, day, hour), "");
+                }
+            }
+        }
+        store
+    }
+
+    fn request(prefix: &str, start_after: Option<&str>, max_keys: Option<u32>, delimiter: Option<&str>) -> ListObjectsRequest {
+        ListObjectsRequest {
+            prefix: prefix.to_string(),
+            start_after: start_after.map(str::to_string),
+            max_keys,
+            delimiter: delimiter.map(str::to_string),
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+        }
+    }
+
+    /// Lists every page of `request`.
+    fn list_all(store: &MemoryPrefixStore, request: &ListObjectsRequest) -> (Vec<String>, Vec<String>, usize) {
+        let (mut contents, mut common, mut pages) = (Vec::new(), Vec::new(), 0);
+        let mut token = None;
+        loop {
+            let page = store.list_objects_v2(request, token.as_deref()).unwrap();
+            pages += 1;
+            contents.extend(page.contents);
+            common.extend(page.common_prefixes);
+            token = page.next_continuation_token;
+            if token.is_none() {
+                return (contents, common, pages);
+            }
+        }
+    }
+
+    #[test]
+    fn lists_pages_like_s3() {
+        let store = days();
+
+        let (contents, common, pages) = list_all(&store, &request("2017/03/", Some("2017/03/27/00"), Some(2), None));
+        assert_eq!(contents, vec!["2017/03/27/12", "2017/03/28/00", "2017/03/28/12"]);
+        assert!(common.is_empty());
+        assert_eq!(pages, 2);
+
+        let page = store.list_objects_v2(&request("2017/", None, None, Some("/")), None).unwrap();
+        assert_eq!(page.common_prefixes, vec!["2017/01/", "2017/02/", "2017/03/", "2017/04/", "2017/05/", "2017/06/"]);
+        assert!(!page.is_truncated());
+
+        let (contents, common, pages) = list_all(&store, &request("2017/0", Some("2017/02/"), Some(4), Some("/")));
+        assert!(contents.is_empty());
+        assert_eq!(common, vec!["2017/02/", "2017/03/", "2017/04/", "2017/05/", "2017/06/"]);
+        assert_eq!(pages, 2);
+
+        let (contents, _, pages) = list_all(&store, &request("", None, None, None));
+        assert_eq!(contents.len(), store.len());
+        assert_eq!(pages, 1 + (store.len() - 1) / MAX_KEYS);
+
+        assert_eq!(store.list_objects_v2(&request("", None, Some(0), None), None), Ok(ListObjectsPage::default()));
+        assert_eq!(store.list_objects_v2(&request("", None, None, None), Some("zz")), Err(StoreError::InvalidContinuationToken));
+        assert_eq!(store.list_objects_v2(&request("", None, None, None), Some("")), Err(StoreError::InvalidContinuationToken));
+    }
+    #[test]
+    fn binds_tokens_to_their_request() {
+        let store = days();
+        let first = request("2017/03/", Some("2017/03/10/"), Some(2), None);
+        let token = store.list_objects_v2(&first, None).unwrap().next_continuation_token.unwrap();
+
+        let page = store.list_objects_v2(&first, Some(&token)).unwrap();
+        assert_eq!(page.contents, vec!["2017/03/11/00", "2017/03/11/12"]);
+        // other delimiters and page sizes may continue the listing
+        assert!(store.list_objects_v2(&request("2017/03/", Some("2017/03/10/"), Some(5), Some("/")), Some(&token)).is_ok());
+
+        for other in &[request("2017/04/", Some("2017/03/10/"), Some(2), None), request("2017/03/", None, Some(2), None), request("2017/03/", Some("2017/03/11/"), Some(2), None)] {
+            assert_eq!(store.list_objects_v2(other, Some(&token)), Err(StoreError::ContinuationTokenMismatch));
+        }
+        let mut altered = token.clone();
+        altered.truncate(token.len() - 2);
+        assert_eq!(store.list_objects_v2(&first, Some(&altered)), Err(StoreError::InvalidContinuationToken));
+    }
+    #[test]
+    fn skips_keys_s3_cannot_hold() {
+        let store: MemoryPrefixStore = vec![(&b"a"[..], &b""[..]), (b"a\xff", b""), (b"b", b"")].into_iter().collect();
+
+        let (contents, _, _) = list_all(&store, &request("", None, Some(1), None));
+
+        assert_eq!(contents, vec!["a", "b"]);
+    }
+    #[test]
+    fn scans_like_redis() {
+        let store = days();
+
+        let mut cursor = 0;
+        let mut seen = Vec::new();
+        let mut calls = 0;
+        loop {
+            let (next, keys) = store.scan(cursor, None, 10);
+            seen.extend(keys);
+            calls += 1;
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        let unique: BTreeSet<&Vec<u8>> = seen.iter().collect();
+        assert_eq!(unique.len(), store.len());
+        assert!(calls > 1);
+        assert!(seen.windows(2).any(|w| w[0] > w[1]));
+    }
+    #[test]
+    fn scan_survives_a_shrinking_store() {
+        let mut store = days();
+        let (mut cursor, mut seen) = store.scan(0, None, 50);
+
+        // drop most keys mid-scan, shrinking the table
+        let doomed: Vec<Vec<u8>> = store.keys().filter(|k| !k.starts_with(b"2017/01/")).map(|k| k.to_vec()).collect();
+        for key in &doomed {
+            store.remove(key);
+        }
+        while cursor != 0 {
+            let (next, keys) = store.scan(cursor, None, 50);
+            seen.extend(keys);
+            cursor = next;
+        }
+
+        let seen: BTreeSet<Vec<u8>> = seen.into_iter().collect();
+        assert!(store.keys().all(|k| seen.contains(k)));
+    }
+    #[test]
+    fn covers_match_end_to_end() {
+        let store = days();
+        let expected: Vec<&[u8]> = store.keys().filter(|k| &k[..] >= b"2017/02/27/12" && &k[..] <= b"2017/04/02/00").collect();
+
+        // Redis: one scan per pattern, merged and sorted
+        let cover = range_cover(b"2017/02/27/12", b"2017/04/02/00").unwrap();
+        let scans = scan_patterns(&cover).into_iter().map(|pattern| {
+            let mut keys = Vec::new();
+            let mut cursor = 0;
+            loop {
+                let (next, batch) = store.scan(cursor, Some(&pattern), 7);
+                keys.extend(batch);
+                cursor = next;
+                if cursor == 0 {
+                    return keys;
+                }
+            }
+        });
+        let merged: Vec<Vec<u8>> = merge_unordered(scans, store.len()).unwrap().collect();
+        assert_eq!(merged, expected);
+
+        // S3: one paginated listing that stops at the end of the range
+        let plan = plan_range("2017/02/27/12"..="2017/04/02/00", Some(5), None).unwrap();
+        let mut listed = Vec::new();
+        let mut token = None;
+        'pages: loop {
+            let page = store.list_objects_v2(&plan[0], token.as_deref()).unwrap();
+            for key in page.contents {
+                if plan[0].is_past_end(&key) {
+                    break 'pages;
+                }
+                if plan[0].contains(&key) {
+                    listed.push(key);
+                }
+            }
+            token = page.next_continuation_token;
+            if token.is_none() {
+                break;
+            }
+        }
+        assert_eq!(listed.iter().map(|k| k.as_bytes()).collect::<Vec<_>>(), expected);
+    }
+}
@@ -0,0 +1,338 @@
+//! Key-value stores that answer prefix queries.
+//!
+//! [`MemoryPrefixStore`] emulates the listing semantics of S3 and the `SCAN` semantics of
+//! Redis in process, so covers and plans can be tested end to end without either.
+//! [`FsPrefixStore`] serves the files of a local directory tree laid out like S3 keys.
+//!
+//! Both implement [`PrefixStore`], through which [`range_query`] runs a range against
+//! any backend. [`stream_range`] runs one against an [`AsyncPrefixStore`], listing
+//! several prefixes at once, and [`range_page`] one page at a time, resuming from a
+//! signed [`RangeCursor`].
+
+use std::ops::RangeBounds;
+
+use error::QueryError;
+use key_range::{self, KeyRange};
+use lex::{self, LexPrefix};
+
+mod cursor;
+mod fs;
+mod memory;
+mod stream;
+
+pub use self::cursor::{range_page, CursorKey, RangeCursor, RangePage};
+pub use self::fs::FsPrefixStore;
+pub use self::memory::{ListObjectsPage, MemoryPrefixStore};
+pub use self::stream::{stream_range, AsyncPrefixStore, RangeStream, StreamOptions};
+
+/// The most keys [`range_query`] asks for in one page.
+pub(crate) const PAGE_LIMIT: usize = 1000;
+/// The most prefixes listed for a range by a store that is
+/// [ordered](struct.Capabilities.html#structfield.ordered).
+pub(crate) const MAX_PREFIXES: usize = 16;
+
+/// What a [`PrefixStore`] can do beyond listing keys by prefix.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Capabilities {
+    /// Pages list keys in ascending order and no key is listed twice.
+    pub ordered: bool,
+    /// The store can roll keys up into common prefixes at a delimiter, as S3 does.
+    ///
+    /// This is informational: range queries list prefixes without a delimiter.
+    pub delimiter: bool,
+    /// [`PrefixStore::list_range`] scans the range itself instead of a prefix holding it.
+    pub native_range: bool,
+}
+
+/// One page of keys listed by a [`PrefixStore`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyPage {
+    /// The keys listed.
+    pub keys: Vec<Vec<u8>>,
+    /// The cursor for the next page, or `None` if this is the last page.
+    pub cursor: Option<Vec<u8>>,
+}
+
+/// A backend that lists its keys by prefix, one page at a time.
+pub trait PrefixStore {
+    /// The error returned when listing fails.
+    type Error;
+
+    /// Returns what the store can do.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Lists one page of the keys starting with `prefix`.
+    ///
+    /// `cursor` is `None` for the first page and the previous page's cursor after that.
+    /// A page holds at most `limit` keys, but at least one is asked for even if `limit`
+    /// is zero. Pages may be empty while there are more to come.
+    fn list_prefix(&self, prefix: &[u8], cursor: Option<&[u8]>, limit: usize) -> Result<KeyPage, Self::Error>;
+
+    /// Lists one page of the keys within `range`, paged like [`list_prefix`].
+    ///
+    /// By default the longest prefix shared by every key in the range is listed, and keys
+    /// outside the range are dropped from each page.
+    ///
+    /// [`list_prefix`]: #tymethod.list_prefix
+    fn list_range(&self, range: &KeyRange<Vec<u8>>, cursor: Option<&[u8]>, limit: usize) -> Result<KeyPage, Self::Error> {
+        let mut page = self.list_prefix(shared_prefix(range), cursor, limit)?;
+        page.keys.retain(|key| range.contains(key));
+        Ok(page)
+    }
+}
+
+/// Lists every key of a store within `range`, in ascending order.
+///
+/// Stores with [native range scans](struct.Capabilities.html#structfield.native_range)
+/// scan the range directly. Other stores list a few prefixes that hold the range's
+/// [cover](../lex/fn.range_cover.html), and keys outside the range are dropped. Stores
+/// that aren't [ordered](struct.Capabilities.html#structfield.ordered) list a single
+/// prefix, since each listing may scan every key, and their results are sorted and
+/// deduplicated.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::store::{range_query, MemoryPrefixStore};
+///
+/// let store: MemoryPrefixStore = (5..25).map(|i| (format!("user:{:02}", i), "")).collect();
+///
+/// let keys = range_query(&store, "user:10".."user:13").unwrap();
+///
+/// keys.len();
+/// // 3
+/// String::from_utf8_lossy(&keys[0]);
+/// // "user:10"
+/// ```
+pub fn range_query<S, K, R>(store: &S, range: R) -> Result<Vec<Vec<u8>>, QueryError<S::Error>>
+where
+    S: PrefixStore + ?Sized,
+    K: AsRef<[u8]>,
+    R: RangeBounds<K>,
+{
+    let start = range.start_bound().map(K::as_ref);
+    let end = range.end_bound().map(K::as_ref);
+    let cover = lex::bounds_cover(start, end).map_err(QueryError::Range)?;
+    let capabilities = store.capabilities();
+
+    let mut keys = Vec::new();
+    if capabilities.native_range {
+        for range in key_range::lex_ranges(&cover) {
+            let mut cursor = None;
+            loop {
+                let page = store.list_range(&range, cursor.as_deref(), PAGE_LIMIT).map_err(QueryError::Store)?;
+                keys.extend(page.keys);
+                cursor = match page.cursor {
+                    Some(next) => Some(next),
+                    None => break,
+                };
+            }
+        }
+    } else {
+        let max_prefixes = if capabilities.ordered { MAX_PREFIXES } else { 1 };
+        for entry in coarse_cover(&cover, max_prefixes) {
+            let mut cursor = None;
+            loop {
+                let page = store.list_prefix(entry.bytes(), cursor.as_deref(), PAGE_LIMIT).map_err(QueryError::Store)?;
+                keys.extend(page.keys.into_iter().filter(|key| (start, end).contains(&key[..])));
+                cursor = match page.cursor {
+                    Some(next) => Some(next),
+                    None => break,
+                };
+            }
+        }
+    }
+
+    if !capabilities.ordered {
+        keys.sort_unstable();
+        keys.dedup();
+    }
+    Ok(keys)
+}
+
+/// Merges a lexicographic cover into at most `max_prefixes` prefixes holding every key it matches.
+///
+/// The entries of an exact cover are mostly runs of siblings along the paths to the range's
+/// bounds. Each entry is cut back to one byte past the prefix shared by the whole cover, which
+/// merges every run into the child of that prefix holding it. Keys near the bounds but outside
+/// the range then match too, and must be dropped by the caller. If that still leaves too many
+/// prefixes, or the cover matches the shared prefix as a key, the shared prefix itself is
+/// returned: listing that key would list every key below it, which the other prefixes hold.
+pub(crate) fn coarse_cover(cover: &[LexPrefix], max_prefixes: usize) -> Vec<LexPrefix> {
+    let (first, last) = match (cover.first(), cover.last()) {
+        (Some(first), Some(last)) => (first.bytes(), last.bytes()),
+        _ => return Vec::new(),
+    };
+    let base_len = first.iter().zip(last).take_while(|&(a, b)| a == b).count();
+
+    let mut coarse: Vec<LexPrefix> = Vec::new();
+    for entry in cover {
+        let entry = match entry.bytes() {
+            bytes if bytes.len() > base_len => LexPrefix::Prefix(bytes[..=base_len].to_vec()),
+            _ if cover.len() > 1 => return vec![LexPrefix::Prefix(first[..base_len].to_vec())],
+            _ => entry.clone(),
+        };
+        if coarse.last() != Some(&entry) {
+            coarse.push(entry);
+        }
+    }
+    if coarse.len() > max_prefixes.max(1) {
+        return vec![LexPrefix::Prefix(first[..base_len].to_vec())];
+    }
+    coarse
+}
+
+/// Returns the longest prefix of every key in `range`.
+fn shared_prefix(range: &KeyRange<Vec<u8>>) -> &[u8] {
+    let len = match range.end {
+        Some(ref end) => range.start.iter().zip(end).take_while(|&(a, b)| a == b).count(),
+        // only keys starting with the start's leading 0xff bytes are above it
+        None => range.start.iter().take_while(|&&b| b == 0xff).count(),
+    };
+    &range.start[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glob;
+    use std::convert::TryInto;
+    use std::ops::Bound;
+
+    /// Lists a memory store with `SCAN`, which is unordered and may repeat keys.
+    struct Scan(MemoryPrefixStore);
+
+    impl PrefixStore for Scan {
+        type Error = ();
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::default()
+        }
+
+        fn list_prefix(&self, prefix: &[u8], cursor: Option<&[u8]>, limit: usize) -> Result<KeyPage, ()> {
+            let cursor = match cursor {
+                Some(bytes) => u64::from_be_bytes(bytes.try_into().map_err(|_| ())?),
+                None => 0,
+            };
+            let (next, keys) = self.0.scan(cursor, Some(&glob::prefix_patterns(&[prefix])[0]), limit);
+            Ok(KeyPage {
+                keys,
+                cursor: Some(next.to_be_bytes().to_vec()).filter(|_| next != 0),
+            })
+        }
+    }
+
+    /// Lists a memory store by prefix only, in order.
+    struct Ordered(MemoryPrefixStore);
+
+    impl PrefixStore for Ordered {
+        type Error = ();
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                ordered: true,
+                ..Capabilities::default()
+            }
+        }
+
+        fn list_prefix(&self, prefix: &[u8], cursor: Option<&[u8]>, limit: usize) -> Result<KeyPage, ()> {
+            Ok(self.0.list_prefix(prefix, cursor, limit).unwrap())
+        }
+    }
+
+    fn keys() -> Vec<Vec<u8>> {
+        let symbols = [0u8, b'a', 0xff];
+        let mut keys = Vec::new();
+        for &a in &symbols {
+            keys.push(vec![a]);
+            for &b in &symbols {
+                keys.push(vec![a, b]);
+                keys.push(vec![a, b, b'z']);
+            }
+        }
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn finds_shared_prefixes() {
+        let range = |start: &[u8], end: Option<&[u8]>| KeyRange {
+            start: start.to_vec(),
+            end: end.map(|end| end.to_vec()),
+        };
+
+        assert_eq!(shared_prefix(&range(b"user:10", Some(b"user:2"))), b"user:");
+        assert_eq!(shared_prefix(&range(b"user", Some(b"user\0"))), b"user");
+        assert_eq!(shared_prefix(&range(b"a", Some(b"b"))), b"");
+        assert_eq!(shared_prefix(&range(b"\xff\xffa", None)), b"\xff\xff");
+        assert_eq!(shared_prefix(&range(b"a", None)), b"");
+    }
+    #[test]
+    fn merges_covers_into_few_prefixes() {
+        let prefixes = |lo: &str, hi: &str, max_prefixes| -> Vec<String> {
+            let cover = lex::range_cover(lo.as_bytes(), hi.as_bytes()).unwrap();
+            coarse_cover(&cover, max_prefixes).iter().map(|entry| String::from_utf8_lossy(entry.bytes()).into_owned()).collect()
+        };
+
+        assert_eq!(prefixes("user:10", "user:20", 16), vec!["user:1", "user:2"]);
+        assert_eq!(prefixes("k:10", "k:69", 16), vec!["k:1", "k:2", "k:3", "k:4", "k:5", "k:6"]);
+        assert_eq!(prefixes("k:10", "k:69", 4), vec!["k:"]);
+        assert_eq!(prefixes("user:15", "user:15", 16), vec!["user:15"]);
+        assert_eq!(prefixes("a", "a\u{7f}", 16), vec!["a"]);
+        assert_eq!(prefixes("ab", "ab\u{1}", 16), vec!["ab"]);
+        assert_eq!(prefixes("ab\0", "ab\0z", 16), vec!["ab\0"]);
+        assert!(coarse_cover(&[], 16).is_empty());
+    }
+    #[test]
+    fn queries_every_kind_of_store() {
+        let keys = keys();
+        let memory: MemoryPrefixStore = keys.iter().map(|key| (key.clone(), "")).collect();
+        let scan = Scan(memory.clone());
+        let ordered = Ordered(memory.clone());
+
+        for lo in &keys {
+            for hi in keys.iter().filter(|hi| lo <= *hi) {
+                let range = (Bound::Excluded(lo), Bound::Included(hi));
+                let expected: Vec<Vec<u8>> = keys.iter().filter(|key| range.contains(key)).cloned().collect();
+
+                assert_eq!(range_query::<_, Vec<u8>, _>(&memory, range).unwrap(), expected);
+                assert_eq!(range_query::<_, Vec<u8>, _>(&scan, range).unwrap(), expected);
+                assert_eq!(range_query::<_, Vec<u8>, _>(&ordered, range).unwrap(), expected);
+                assert_eq!(range_query::<_, Vec<u8>, _>(&ordered, lo..=hi).unwrap(), keys.iter().filter(|key| (lo..=hi).contains(key)).cloned().collect::<Vec<_>>());
+            }
+        }
+        assert_eq!(range_query::<_, Vec<u8>, _>(&memory, ..).unwrap(), keys);
+        assert_eq!(range_query(&scan, b"a".to_vec()..).unwrap().len(), keys.iter().filter(|key| key[0] >= b'a').count());
+    }
+    #[test]
+    fn lists_a_lower_bound_that_prefixes_the_upper_bound_once() {
+        let ordered = Ordered([&b"ab"[..], b"ab\0", b"ab\0z", b"ab\x01", b"ab\x01z", b"ac"].iter().map(|key| (key.to_vec(), "")).collect());
+        let expected = vec![b"ab".to_vec(), b"ab\0".to_vec(), b"ab\0z".to_vec(), b"ab\x01".to_vec()];
+
+        assert_eq!(range_query(&ordered, &b"ab"[..]..=&b"ab\x01"[..]).unwrap(), expected);
+        assert_eq!(range_query(&ordered, &b"ab"[..]..&b"ab\x01z"[..]).unwrap(), expected);
+    }
+    #[test]
+    fn falls_back_to_listing_the_shared_prefix() {
+        let scan = Scan(keys().into_iter().map(|key| (key, "")).collect());
+        let range = KeyRange {
+            start: b"aa".to_vec(),
+            end: Some(b"b".to_vec()),
+        };
+
+        let mut listed = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = scan.list_range(&range, cursor.as_deref(), 3).unwrap();
+            listed.extend(page.keys);
+            cursor = match page.cursor {
+                Some(next) => Some(next),
+                None => break,
+            };
+        }
+        listed.sort();
+
+        assert_eq!(listed, vec![b"aa".to_vec(), b"aaz".to_vec(), b"a\xff".to_vec(), b"a\xffz".to_vec()]);
+        assert_eq!(range_query(&scan, b"b".to_vec()..b"a".to_vec()), Err(QueryError::Range(::RangePrefixError::InvertedRange)));
+    }
+}
@@ -0,0 +1,563 @@
+//! Range queries against asynchronous stores, as streams of keys.
+//!
+//! A [`RangeStream`] lists several prefixes at once. In order, a prefix that isn't the
+//! earliest one still listing holds at most one page of keys until its turn comes, so
+//! memory stays bounded by the concurrency times the page size.
+
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use error::RangePrefixError;
+use lex::{self, LexPrefix};
+use store::{coarse_cover, Capabilities, KeyPage, MAX_PREFIXES, PAGE_LIMIT};
+
+/// A backend that lists its keys by prefix asynchronously, one page at a time.
+///
+/// This is the asynchronous counterpart of [`PrefixStore`](trait.PrefixStore.html), with the
+/// same paging. Arguments are owned so the returned future doesn't borrow them.
+pub trait AsyncPrefixStore {
+    /// The error returned when listing fails.
+    type Error;
+    /// The future resolving to one page of keys.
+    type Future: Future<Output = Result<KeyPage, Self::Error>>;
+
+    /// Returns what the store can do.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Starts listing one page of the keys starting with `prefix`.
+    fn list_prefix(&self, prefix: Vec<u8>, cursor: Option<Vec<u8>>, limit: usize) -> Self::Future;
+}
+
+impl<S: AsyncPrefixStore + ?Sized> AsyncPrefixStore for &S {
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn capabilities(&self) -> Capabilities {
+        (**self).capabilities()
+    }
+
+    fn list_prefix(&self, prefix: Vec<u8>, cursor: Option<Vec<u8>>, limit: usize) -> S::Future {
+        (**self).list_prefix(prefix, cursor, limit)
+    }
+}
+
+/// How [`stream_range`] runs a query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StreamOptions {
+    /// The most prefixes listed at once.
+    pub concurrency: usize,
+    /// Yield keys in ascending order, holding back keys of later prefixes until earlier
+    /// ones are done. Otherwise keys are yielded as soon as their page arrives.
+    pub ordered: bool,
+    /// The most keys yielded, after which outstanding listings are cancelled.
+    pub limit: Option<usize>,
+}
+
+impl Default for StreamOptions {
+    fn default() -> StreamOptions {
+        StreamOptions {
+            concurrency: 8,
+            ordered: true,
+            limit: None,
+        }
+    }
+}
+
+/// Lists the keys of an asynchronous store within `range` as a [`Stream`].
+///
+/// The prefixes [`range_query`](fn.range_query.html) would list are listed page by page,
+/// with up to `options.concurrency` prefixes in flight at once, and keys outside the range
+/// are dropped. A key is never yielded twice, even by stores that aren't
+/// [ordered](struct.Capabilities.html#structfield.ordered).
+///
+/// The stream ends after the first error, or once `options.limit` keys have been yielded,
+/// at which point the listings still in flight are dropped. Dropping the stream cancels
+/// them too.
+pub fn stream_range<S, K, R>(store: S, range: R, options: StreamOptions) -> Result<RangeStream<S>, RangePrefixError>
+where
+    S: AsyncPrefixStore,
+    K: AsRef<[u8]>,
+    R: RangeBounds<K>,
+{
+    let start = range.start_bound().map(|key| key.as_ref().to_vec());
+    let end = range.end_bound().map(|key| key.as_ref().to_vec());
+    let cover = lex::bounds_cover(start.as_ref().map(Vec::as_slice), end.as_ref().map(Vec::as_slice))?;
+    let capabilities = store.capabilities();
+    let max_prefixes = if capabilities.ordered { MAX_PREFIXES } else { 1 };
+    Ok(RangeStream {
+        capabilities,
+        store,
+        range: (start, end),
+        cover: coarse_cover(&cover, max_prefixes).into(),
+        options,
+        listings: VecDeque::new(),
+        ready: VecDeque::new(),
+        yielded: 0,
+        done: false,
+    })
+}
+
+/// The stream returned by [`stream_range`].
+pub struct RangeStream<S: AsyncPrefixStore> {
+    store: S,
+    capabilities: Capabilities,
+    range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    /// The prefixes not yet started.
+    cover: VecDeque<LexPrefix>,
+    options: StreamOptions,
+    /// The prefixes started and not yet drained, in cover order.
+    listings: VecDeque<Listing<S::Future>>,
+    /// Keys to yield, in order.
+    ready: VecDeque<Vec<u8>>,
+    yielded: usize,
+    done: bool,
+}
+
+// the store is never pinned and the listing futures are boxed
+impl<S: AsyncPrefixStore> Unpin for RangeStream<S> {}
+
+/// The listing of one prefix.
+struct Listing<F> {
+    entry: LexPrefix,
+    /// The page in flight.
+    page: Option<Pin<Box<F>>>,
+    /// The cursor of the next page while it waits to be fetched.
+    cursor: Option<Vec<u8>>,
+    /// Keys listed but not yet yielded.
+    keys: Vec<Vec<u8>>,
+    /// Keys listed so far, for stores that may list a key twice.
+    seen: HashSet<Vec<u8>>,
+}
+
+impl<F> Listing<F> {
+    /// Returns `true` once the last page has arrived.
+    fn is_done(&self) -> bool {
+        self.page.is_none() && self.cursor.is_none()
+    }
+}
+
+impl<S: AsyncPrefixStore> RangeStream<S> {
+    /// Returns the number of keys yielded so far.
+    pub fn yielded(&self) -> usize {
+        self.yielded
+    }
+
+    /// Returns the number of prefixes being listed.
+    pub fn in_flight(&self) -> usize {
+        self.listings.iter().filter(|listing| !listing.is_done()).count()
+    }
+
+    /// Returns the number of keys that may still be yielded.
+    fn remaining(&self) -> usize {
+        self.options.limit.map_or(usize::MAX, |limit| limit.saturating_sub(self.yielded))
+    }
+
+    /// Starts listing prefixes until the concurrency limit is reached.
+    fn start(&mut self) {
+        let concurrency = self.options.concurrency.max(1);
+        // in order, finished prefixes still hold a place until their keys are yielded
+        let busy = if self.options.ordered { self.listings.len() } else { self.in_flight() };
+        for _ in busy..concurrency {
+            let entry = match self.cover.pop_front() {
+                Some(entry) => entry,
+                None => return,
+            };
+            let page = self.store.list_prefix(entry.bytes().to_vec(), None, self.page_limit());
+            self.listings.push_back(Listing {
+                entry,
+                page: Some(Box::pin(page)),
+                cursor: None,
+                keys: Vec::new(),
+                seen: HashSet::new(),
+            });
+        }
+    }
+
+    fn page_limit(&self) -> usize {
+        self.remaining().min(PAGE_LIMIT)
+    }
+
+    /// Fetches the next page of each listing that may buffer more keys.
+    ///
+    /// In order, only the earliest listing and listings whose keys have all been yielded
+    /// fetch, so the others hold at most one page.
+    fn fetch(&mut self) {
+        let page_limit = self.page_limit();
+        let store = &self.store;
+        let ordered = self.options.ordered;
+        for (i, listing) in self.listings.iter_mut().enumerate() {
+            if ordered && i > 0 && !listing.keys.is_empty() {
+                continue;
+            }
+            if let Some(cursor) = listing.cursor.take() {
+                listing.page = Some(Box::pin(store.list_prefix(listing.entry.bytes().to_vec(), Some(cursor), page_limit)));
+            }
+        }
+    }
+
+    /// Polls every page in flight, returning `true` if any arrived.
+    fn poll_pages(&mut self, cx: &mut Context) -> Result<bool, S::Error> {
+        let mut progressed = false;
+        let range = &self.range;
+        let ordered = self.capabilities.ordered;
+        for listing in &mut self.listings {
+            let page = match listing.page {
+                Some(ref mut page) => match page.as_mut().poll(cx) {
+                    Poll::Ready(page) => page?,
+                    Poll::Pending => continue,
+                },
+                None => continue,
+            };
+            progressed = true;
+
+            for key in page.keys {
+                if range.contains(&key) && (ordered || listing.seen.insert(key.clone())) {
+                    listing.keys.push(key);
+                }
+            }
+            listing.page = None;
+            listing.cursor = page.cursor;
+        }
+        Ok(progressed)
+    }
+
+    /// Moves the keys that may be yielded now to the ready queue.
+    fn collect(&mut self) {
+        if !self.options.ordered {
+            for listing in &mut self.listings {
+                self.ready.extend(listing.keys.drain(..));
+            }
+            self.listings.retain(|listing| !listing.is_done());
+            return;
+        }
+
+        while let Some(head) = self.listings.front_mut() {
+            if !head.is_done() {
+                // an ordered store's keys can be yielded as they arrive
+                if self.capabilities.ordered {
+                    self.ready.extend(head.keys.drain(..));
+                }
+                return;
+            }
+            let mut keys = mem::take(&mut head.keys);
+            keys.sort_unstable();
+            self.ready.extend(keys);
+            self.listings.pop_front();
+        }
+    }
+
+    /// Ends the stream, dropping the pages in flight.
+    fn finish(&mut self) {
+        self.done = true;
+        self.cover.clear();
+        self.listings.clear();
+        self.ready.clear();
+    }
+}
+
+impl<S: AsyncPrefixStore> Stream for RangeStream<S> {
+    type Item = Result<Vec<u8>, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            if this.remaining() == 0 {
+                this.finish();
+                return Poll::Ready(None);
+            }
+            if let Some(key) = this.ready.pop_front() {
+                this.yielded += 1;
+                if this.remaining() == 0 {
+                    this.finish();
+                }
+                return Poll::Ready(Some(Ok(key)));
+            }
+
+            this.start();
+            this.fetch();
+            if this.listings.is_empty() {
+                this.finish();
+                return Poll::Ready(None);
+            }
+            match this.poll_pages(cx) {
+                Ok(progressed) => {
+                    this.collect();
+                    if !progressed && this.ready.is_empty() {
+                        return Poll::Pending;
+                    }
+                }
+                Err(error) => {
+                    this.finish();
+                    return Poll::Ready(Some(Err(error)));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.ready.len().min(self.remaining()), self.options.limit.map(|_| self.remaining()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+    use std::thread::{self, Thread};
+    use std::time::{Duration, Instant};
+
+    use store::{range_query, MemoryPrefixStore, PrefixStore};
+
+    #[derive(Debug, Default)]
+    struct Counters {
+        started: AtomicUsize,
+        finished: AtomicUsize,
+        cancelled: AtomicUsize,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    /// A store that answers each listing after a delay that depends on the prefix.
+    struct SlowStore {
+        keys: MemoryPrefixStore,
+        ordered: bool,
+        page_size: usize,
+        fail: Option<&'static [u8]>,
+        counters: Arc<Counters>,
+    }
+
+    impl SlowStore {
+        fn new(ordered: bool) -> SlowStore {
+            SlowStore {
+                keys: (0..100).map(|i| (format!("k:{:02}", i), "")).collect(),
+                ordered,
+                page_size: 3,
+                fail: None,
+                counters: Arc::default(),
+            }
+        }
+    }
+
+    impl AsyncPrefixStore for SlowStore {
+        type Error = ();
+        type Future = Delayed;
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                ordered: self.ordered,
+                ..Capabilities::default()
+            }
+        }
+
+        fn list_prefix(&self, prefix: Vec<u8>, cursor: Option<Vec<u8>>, limit: usize) -> Delayed {
+            let mut page = self.keys.list_prefix(&prefix, cursor.as_deref(), limit.min(self.page_size)).unwrap();
+            if !self.ordered {
+                // out of order, and repeating a key as SCAN may
+                page.keys.reverse();
+                page.keys.extend(page.keys.first().cloned());
+            }
+            let counters = self.counters.clone();
+            counters.started.fetch_add(1, Ordering::SeqCst);
+            let in_flight = counters.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            counters.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+
+            // later prefixes tend to answer first
+            let latency = Duration::from_micros(200 * (10 - u64::from(prefix.last().unwrap() % 10)));
+            Delayed {
+                page: Some(if self.fail == Some(&prefix[..]) { Err(()) } else { Ok(page) }),
+                until: Instant::now() + latency,
+                timer: false,
+                counters,
+            }
+        }
+    }
+
+    struct Delayed {
+        page: Option<Result<KeyPage, ()>>,
+        until: Instant,
+        timer: bool,
+        counters: Arc<Counters>,
+    }
+
+    impl Future for Delayed {
+        type Output = Result<KeyPage, ()>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<KeyPage, ()>> {
+            if Instant::now() >= self.until {
+                self.counters.finished.fetch_add(1, Ordering::SeqCst);
+                self.counters.in_flight.fetch_sub(1, Ordering::SeqCst);
+                return Poll::Ready(self.page.take().unwrap());
+            }
+            if !self.timer {
+                self.timer = true;
+                let (waker, until) = (cx.waker().clone(), self.until);
+                thread::spawn(move || {
+                    thread::sleep(until.saturating_duration_since(Instant::now()));
+                    waker.wake();
+                });
+            }
+            Poll::Pending
+        }
+    }
+
+    impl Drop for Delayed {
+        fn drop(&mut self) {
+            if self.page.is_some() {
+                self.counters.cancelled.fetch_add(1, Ordering::SeqCst);
+                self.counters.in_flight.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Runs a stream to its end on the current thread.
+    fn collect<S: Stream + Unpin>(stream: &mut S) -> Vec<S::Item> {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut items = Vec::new();
+        loop {
+            match Pin::new(&mut *stream).poll_next(&mut cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => return items,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn streams_the_range() {
+        for &store_ordered in &[true, false] {
+            let store = SlowStore::new(store_ordered);
+            let expected = range_query(&store.keys, "k:05"..="k:73").unwrap();
+
+            for &ordered in &[true, false] {
+                let options = StreamOptions {
+                    concurrency: 4,
+                    ordered,
+                    limit: None,
+                };
+                let mut keys: Vec<Vec<u8>> = collect(&mut stream_range(&store, "k:05"..="k:73", options).unwrap())
+                    .into_iter()
+                    .map(Result::unwrap)
+                    .collect();
+                if !ordered {
+                    keys.sort();
+                }
+                assert_eq!(keys, expected);
+            }
+        }
+    }
+    #[test]
+    fn streams_a_range_whose_lower_bound_prefixes_its_upper_bound() {
+        for &store_ordered in &[true, false] {
+            let mut store = SlowStore::new(store_ordered);
+            store.keys = [&b"ab"[..], b"ab\0", b"ab\0z", b"ab\x01", b"ab\x01z", b"ac"].iter().map(|key| (key.to_vec(), "")).collect();
+            let expected = vec![b"ab".to_vec(), b"ab\0".to_vec(), b"ab\0z".to_vec(), b"ab\x01".to_vec()];
+
+            for &ordered in &[true, false] {
+                let options = StreamOptions {
+                    concurrency: 4,
+                    ordered,
+                    limit: None,
+                };
+                let mut keys: Vec<Vec<u8>> = collect(&mut stream_range(&store, &b"ab"[..]..=&b"ab\x01"[..], options).unwrap())
+                    .into_iter()
+                    .map(Result::unwrap)
+                    .collect();
+                if !ordered {
+                    keys.sort();
+                }
+                assert_eq!(keys, expected);
+            }
+        }
+    }
+    #[test]
+    fn bounds_concurrency() {
+        for &ordered in &[true, false] {
+            let store = SlowStore::new(true);
+            let options = StreamOptions {
+                concurrency: 3,
+                ordered,
+                limit: None,
+            };
+
+            assert_eq!(collect(&mut stream_range(&store, "k:05"..="k:73", options).unwrap()).len(), 69);
+            assert_eq!(store.counters.max_in_flight.load(Ordering::SeqCst), 3);
+        }
+    }
+    #[test]
+    fn cancels_at_the_limit() {
+        let store = SlowStore::new(true);
+        let options = StreamOptions {
+            concurrency: 4,
+            ordered: false,
+            limit: Some(4),
+        };
+
+        let mut stream = stream_range(&store, "k:10"..="k:69", options).unwrap();
+        assert_eq!(collect(&mut stream).len(), 4);
+        assert_eq!(stream.yielded(), 4);
+        assert_eq!(stream.in_flight(), 0);
+
+        let counters = &store.counters;
+        let started = counters.started.load(Ordering::SeqCst);
+        assert_eq!(counters.in_flight.load(Ordering::SeqCst), 0);
+        assert_eq!(counters.finished.load(Ordering::SeqCst) + counters.cancelled.load(Ordering::SeqCst), started);
+        assert!(started < 60 / 3);
+    }
+    #[test]
+    fn holds_one_page_per_waiting_prefix() {
+        let store = SlowStore::new(true);
+        let options = StreamOptions {
+            concurrency: 4,
+            ordered: true,
+            limit: None,
+        };
+        let mut stream = stream_range(&store, "k:10"..="k:69", options).unwrap();
+
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut keys = Vec::new();
+        loop {
+            let buffered: usize = stream.listings.iter().map(|listing| listing.keys.len()).sum::<usize>() + stream.ready.len();
+            assert!(buffered <= 4 * store.page_size, "{} keys buffered", buffered);
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(key)) => keys.push(key.unwrap()),
+                Poll::Ready(None) => break,
+                Poll::Pending => thread::park(),
+            }
+        }
+        assert_eq!(keys, range_query(&store.keys, "k:10"..="k:69").unwrap());
+    }
+    #[test]
+    fn ends_at_the_first_error() {
+        let mut store = SlowStore::new(true);
+        store.fail = Some(b"k:3");
+
+        let mut stream = stream_range(&store, "k:10"..="k:69", StreamOptions::default()).unwrap();
+        let items = collect(&mut stream);
+
+        assert_eq!(items.last(), Some(&Err(())));
+        assert!(items[..items.len() - 1].iter().all(Result::is_ok));
+        assert_eq!(collect(&mut stream), vec![]);
+        assert_eq!(store.counters.in_flight.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,187 @@
+//! Bounds for Redis' lexicographic sorted-set commands.
+//!
+//! When every member of a sorted set has the same score, `ZRANGEBYLEX`, `ZLEXCOUNT` and
+//! `ZREMRANGEBYLEX` select members by byte order, so a range needs no cover at all. Their
+//! bounds are written `[key` (inclusive), `(key` (exclusive), `-` (the smallest member)
+//! and `+` (the largest).
+//!
+//! [`LexRange`] takes the same ranges as [`cover`](../fn.cover.html), so a query can be
+//! sent either as `SCAN` patterns built from the cover or as a single lex range.
+
+use std::ops::{Bound, RangeBounds};
+
+use error::RangePrefixError;
+use lex::{self, LexPrefix};
+
+/// A range of sorted-set members in byte order.
+///
+/// # Example
+///
+/// ```
+/// use binary_prefix::zlex::LexRange;
+///
+/// let range = LexRange::new("user:10".."user:20").unwrap();
+/// (range.min_arg(), range.max_arg());
+/// // ("[user:10", "(user:20")
+///
+/// let range = LexRange::prefix(b"user:");
+/// (range.min_arg(), range.max_arg());
+/// // ("[user:", "(user;")
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LexRange {
+    /// The lower bound.
+    pub min: Bound<Vec<u8>>,
+    /// The upper bound.
+    pub max: Bound<Vec<u8>>,
+}
+
+impl LexRange {
+    /// Creates the range holding the members within `range`.
+    ///
+    /// A range whose start is greater than its end is rejected with
+    /// [`RangePrefixError::InvertedRange`].
+    pub fn new<K: AsRef<[u8]>, R: RangeBounds<K>>(range: R) -> Result<LexRange, RangePrefixError> {
+        let owned = |bound: Bound<&K>| match bound {
+            Bound::Included(key) => Bound::Included(key.as_ref().to_vec()),
+            Bound::Excluded(key) => Bound::Excluded(key.as_ref().to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let range = LexRange {
+            min: owned(range.start_bound()),
+            max: owned(range.end_bound()),
+        };
+        if let (Bound::Included(lo) | Bound::Excluded(lo), Bound::Included(hi) | Bound::Excluded(hi)) = (&range.min, &range.max) {
+            if lo > hi {
+                return Err(RangePrefixError::InvertedRange);
+            }
+        }
+        Ok(range)
+    }
+
+    /// Creates the range holding the members that start with `prefix`.
+    ///
+    /// The range runs from the prefix itself up to its successor, the smallest string
+    /// greater than everything starting with the prefix. A prefix made only of `0xff`
+    /// bytes has no successor and runs up to `+`.
+    pub fn prefix(prefix: &[u8]) -> LexRange {
+        LexRange {
+            min: Bound::Included(prefix.to_vec()),
+            max: match lex::prefix_successor(prefix) {
+                Some(next) => Bound::Excluded(next),
+                None => Bound::Unbounded,
+            },
+        }
+    }
+
+    /// Creates the range holding the members matched by one entry of a lexicographic cover.
+    pub fn entry(entry: &LexPrefix) -> LexRange {
+        match *entry {
+            LexPrefix::Key(ref key) => LexRange {
+                min: Bound::Included(key.clone()),
+                max: Bound::Included(key.clone()),
+            },
+            LexPrefix::Prefix(ref prefix) => LexRange::prefix(prefix),
+        }
+    }
+
+    /// Returns `true` if `member` is within the range.
+    pub fn contains(&self, member: &[u8]) -> bool {
+        let above = match self.min {
+            Bound::Included(ref lo) => member >= &lo[..],
+            Bound::Excluded(ref lo) => member > &lo[..],
+            Bound::Unbounded => true,
+        };
+        let below = match self.max {
+            Bound::Included(ref hi) => member <= &hi[..],
+            Bound::Excluded(ref hi) => member < &hi[..],
+            Bound::Unbounded => true,
+        };
+        above && below
+    }
+
+    /// Returns the `min` argument for `ZRANGEBYLEX`, `ZLEXCOUNT` and `ZREMRANGEBYLEX`.
+    pub fn min_arg(&self) -> Vec<u8> {
+        arg(&self.min, b'-')
+    }
+
+    /// Returns the `max` argument for `ZRANGEBYLEX`, `ZLEXCOUNT` and `ZREMRANGEBYLEX`.
+    ///
+    /// `ZREVRANGEBYLEX` takes this first.
+    pub fn max_arg(&self) -> Vec<u8> {
+        arg(&self.max, b'+')
+    }
+}
+
+fn arg(bound: &Bound<Vec<u8>>, unbounded: u8) -> Vec<u8> {
+    let (mark, key) = match *bound {
+        Bound::Included(ref key) => (b'[', &key[..]),
+        Bound::Excluded(ref key) => (b'(', &key[..]),
+        Bound::Unbounded => return vec![unbounded],
+    };
+    let mut out = Vec::with_capacity(key.len() + 1);
+    out.push(mark);
+    out.extend_from_slice(key);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bounds::cover;
+
+    fn universe() -> Vec<Vec<u8>> {
+        let symbols = [0u8, 1, b'a', 0xfe, 0xff];
+        let mut keys = vec![vec![]];
+        for &a in &symbols {
+            keys.push(vec![a]);
+            for &b in &symbols {
+                keys.push(vec![a, b]);
+            }
+        }
+        keys
+    }
+
+    #[test]
+    fn renders_command_arguments() {
+        let range = LexRange::new(b"a".to_vec()..=b"c".to_vec()).unwrap();
+        assert_eq!((range.min_arg(), range.max_arg()), (b"[a".to_vec(), b"[c".to_vec()));
+
+        let range = LexRange::new::<&str, _>((Bound::Excluded("a"), Bound::Excluded("c"))).unwrap();
+        assert_eq!((range.min_arg(), range.max_arg()), (b"(a".to_vec(), b"(c".to_vec()));
+
+        let range = LexRange::new::<&str, _>(..).unwrap();
+        assert_eq!((range.min_arg(), range.max_arg()), (b"-".to_vec(), b"+".to_vec()));
+
+        assert_eq!(LexRange::new("b".."a"), Err(RangePrefixError::InvertedRange));
+    }
+    #[test]
+    fn prefix_runs_to_its_successor() {
+        assert_eq!(LexRange::prefix(b"ab").max, Bound::Excluded(b"ac".to_vec()));
+        assert_eq!(LexRange::prefix(b"a\xff\xff").max, Bound::Excluded(b"b".to_vec()));
+        assert_eq!(LexRange::prefix(b"\xff\xff").max, Bound::Unbounded);
+        assert_eq!(LexRange::prefix(b"").min_arg(), b"[".to_vec());
+        assert_eq!(LexRange::prefix(b"").max_arg(), b"+".to_vec());
+
+        for prefix in universe() {
+            let range = LexRange::prefix(&prefix);
+            for key in universe() {
+                assert_eq!(range.contains(&key), key.starts_with(&prefix), "{:?} under {:?}", key, prefix);
+            }
+        }
+    }
+    #[test]
+    fn agrees_with_the_cover() {
+        let keys = universe();
+        for lo in &keys {
+            for hi in keys.iter().filter(|hi| lo < *hi) {
+                let range = LexRange::new(lo.clone()..hi.clone()).unwrap();
+                let entries: Vec<LexRange> = cover(lo.clone()..hi.clone()).unwrap().iter().map(LexRange::entry).collect();
+                for key in &keys {
+                    let hits = entries.iter().filter(|entry| entry.contains(key)).count();
+                    assert_eq!(hits, if range.contains(key) { 1 } else { 0 });
+                }
+            }
+        }
+    }
+}